@@ -0,0 +1,66 @@
+use ray_tracing::{geo, ray, simd};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// Times the rays main renders, one per pixel from the eye to the wall, against
+// the unit sphere one at a time, four at once through simd::Ray4 and eight at
+// once through simd::Ray8. All three find the nearest hit without allocating.
+//
+//     cargo bench --bench sphere_scene
+fn main() {
+
+    let pixels = 720;
+    let origin = geo::Point3::new(0.0, 0.0,-5.0);
+    let size   = 10.0/(pixels as geo::Float);
+
+    let rays:Vec<ray::Ray> = (0..pixels*pixels)
+        .map(|i| {
+            let x = -5.0 + size*((i % pixels) as geo::Float);
+            let y =  5.0 - size*((i / pixels) as geo::Float);
+            ray::Ray::new(origin, geo::Point3::new(x, y, 10.0) - origin)
+        })
+        .collect();
+
+    let s = ray::Sphere::unit();
+    let passes = 10;
+
+    let now = Instant::now();
+    let mut scalar_hits = 0;
+    for _ in 0..passes {
+        for r in rays.iter() {
+            if black_box(r).hit(&s).is_finite() {
+                scalar_hits += 1;
+            }
+        }
+    }
+    let scalar = now.elapsed();
+
+    let now = Instant::now();
+    let mut simd4_hits = 0;
+    for _ in 0..passes {
+        for chunk in rays.chunks_exact(4) {
+            let r4 = simd::Ray4::new(black_box(&[chunk[0], chunk[1], chunk[2], chunk[3]]));
+            simd4_hits += r4.hit(&s).iter().filter(|t| t.is_finite()).count();
+        }
+    }
+    let simd4 = now.elapsed();
+
+    let now = Instant::now();
+    let mut simd8_hits = 0;
+    for _ in 0..passes {
+        for chunk in rays.chunks_exact(8) {
+            let r8 = simd::Ray8::new(black_box(&[chunk[0], chunk[1], chunk[2], chunk[3],
+                                                 chunk[4], chunk[5], chunk[6], chunk[7]]));
+            simd8_hits += r8.hit(&s).iter().filter(|t| t.is_finite()).count();
+        }
+    }
+    let simd8 = now.elapsed();
+
+    assert_eq!(scalar_hits, simd4_hits);
+    assert_eq!(scalar_hits, simd8_hits);
+
+    let gain = |t:Duration| scalar.as_secs_f64()/t.as_secs_f64();
+
+    println!("sphere scene, {} rays x {}: scalar {:?}, simd x4 {:?} ({:.2}x), simd x8 {:?} ({:.2}x)",
+             rays.len(), passes, scalar, simd4, gain(simd4), simd8, gain(simd8));
+}
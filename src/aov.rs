@@ -0,0 +1,233 @@
+use crate::geo;
+use crate::ray;
+use crate::rng;
+use crate::bvh;
+use crate::color;
+use crate::scene;
+use crate::canvas;
+use crate::render;
+use crate::progress;
+use crate::integrator;
+
+// The buffers a render can write beside its colour image, for debugging scenes
+// and for compositing. Each is a canvas with one of these per pixel.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum Aov {
+    // distance along the camera ray to the nearest surface, 0 where it missed
+    Depth,
+    // world space normal of that surface facing the camera, x y z as r g b
+    Normal,
+    // the surface's colour before any lighting
+    Albedo,
+    // id of the primitive that was hit as a grey level, -1 where none was
+    Id,
+    // how many times the camera ray crossed a surface, the depth complexity
+    Hits,
+}
+
+impl Aov {
+
+    pub const ALL:[Aov;5] = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::Id, Aov::Hits];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth  => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Id     => "id",
+            Aov::Hits   => "hits",
+        }
+    }
+
+    fn index(&self) -> usize {
+        Aov::ALL.iter().position(|a| a == self).unwrap()
+    }
+}
+
+pub struct Aovs {
+    // in the order of Aov::ALL
+    layers:Vec<canvas::Canvas>,
+}
+
+impl Aovs {
+
+    pub fn new(width:usize, height:usize) -> Aovs {
+        Aovs {layers:Aov::ALL.iter().map(|_| canvas::Canvas::new(width, height)).collect()}
+    }
+
+    pub fn get(&self, aov:Aov) -> &canvas::Canvas {
+        &self.layers[aov.index()]
+    }
+
+    // as a pfm, since depths, normals and ids do not fit a ppm's 0..1
+    pub fn save(&self, aov:Aov, path:&str) {
+        self.get(aov).to_pfm(path);
+    }
+
+    // every buffer to prefix_<name>.pfm
+    pub fn save_all(&self, prefix:&str) {
+        for aov in Aov::ALL.iter() {
+            self.save(*aov, &format!("{}_{}.pfm", prefix, aov.name()));
+        }
+    }
+}
+
+// Renders the scene with one camera ray per pixel from camera, filling image
+// with what the integrator makes of it and aovs with what the ray found.
+// The integrator draws from the generator for sample 0 of each pixel.
+pub fn render<C>(image:&mut canvas::Canvas, aovs:&mut Aovs, scene:&scene::Scene, shading:integrator::Integrator,
+                 settings:&render::Settings, progress:&dyn progress::Progress, camera:C) -> render::Mask
+    where C: Fn(usize, usize) -> ray::Ray + Sync {
+
+    // the colour image goes first, the buffers after it in Aov::ALL order
+    let mut layers = vec![std::mem::replace(image, canvas::Canvas::new(0, 0))];
+    layers.append(&mut aovs.layers);
+
+    let mask = render::render_layers(&mut layers, settings, progress, |t| {
+
+        let (x0, y0, w, h) = (t[0].x, t[0].y, t[0].width, t[0].height);
+
+        for y in y0..y0 + h {
+            for x in x0..x0 + w {
+
+                let r   = camera(x, y);
+                let mut rng = rng::Pcg32::for_sample(settings.seed, x, y, 0);
+                let hit = trace(&mut t[1..], scene, &r, x, y);
+
+                let beauty = match hit {
+                    Some(h) => shading.shade(scene, &r, &h, &mut rng),
+                    None    => shading.miss(scene, &r),
+                };
+
+                t[0].write_pixel(x, y, beauty);
+            }
+        }
+    });
+
+    aovs.layers = layers.split_off(1);
+    *image = layers.pop().unwrap();
+
+    mask
+}
+
+// The same without the colour image, for when that comes from elsewhere such
+// as a progressive render. Nothing is shaded, so it costs one ray per pixel
+// whatever the integrator.
+pub fn render_buffers<C>(aovs:&mut Aovs, scene:&scene::Scene, settings:&render::Settings,
+                         progress:&dyn progress::Progress, camera:C) -> render::Mask
+    where C: Fn(usize, usize) -> ray::Ray + Sync {
+
+    render::render_layers(&mut aovs.layers, settings, progress, |t| {
+
+        let (x0, y0, w, h) = (t[0].x, t[0].y, t[0].width, t[0].height);
+
+        for y in y0..y0 + h {
+            for x in x0..x0 + w {
+                trace(t, scene, &camera(x, y), x, y);
+            }
+        }
+    })
+}
+
+// writes what r finds to the buffers' tiles, in Aov::ALL order, and returns
+// the surface it hit
+fn trace(t:&mut [canvas::Tile], scene:&scene::Scene, r:&ray::Ray, x:usize, y:usize) -> Option<scene::Hit> {
+
+    let grey  = |v:geo::Float| color::Color::new(v, v, v);
+    let black = grey(0.0);
+
+    let all = scene.bvh().intersect(r, &mut bvh::Stats::default());
+    let hit = scene.surface(r, ray::Isect::hit(all.clone()));
+
+    let (depth, normal, albedo, id) = match hit {
+        Some(h) => (grey(h.t*r.dir.len()),
+                    color::Color::new(h.normal.x, h.normal.y, h.normal.z),
+                    h.material.surface_color(),
+                    grey(h.id as geo::Float)),
+        None    => (black, black, black, grey(-1.0)),
+    };
+
+    let crossings = all.iter().filter(|i| i.t > 0.0).count();
+
+    t[Aov::Depth.index()].write_pixel(x, y, depth);
+    t[Aov::Normal.index()].write_pixel(x, y, normal);
+    t[Aov::Albedo.index()].write_pixel(x, y, albedo);
+    t[Aov::Id.index()].write_pixel(x, y, id);
+    t[Aov::Hits.index()].write_pixel(x, y, grey(crossings as geo::Float));
+
+    hit
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape;
+    use crate::matrix;
+    use crate::environment;
+
+    #[test]
+    fn buffers_describe_the_hits() {
+
+        // a red ball in front of a bigger one, looked at straight down z
+        let mut near = ray::Sphere::unit();
+        near.material.color = color::Color::new(1.0, 0.0, 0.0);
+        let far = ray::Sphere::with_transform(matrix::Matrix::translate(0.0, 0.0, 10.0)*matrix::Matrix::scale(4.0, 4.0, 4.0)).unwrap();
+        let (near_id, far_id) = (near.id(), far.id());
+
+        let mut scene = scene::Scene::new(vec![shape::Shape::from(near), shape::Shape::from(far)], vec![]);
+        scene.environment = environment::Environment::Solid(color::Color::new(0.0, 0.0, 1.0));
+
+        // pixel (2,2) looks through both balls, (1,2) at the big one only and
+        // (0,0) at neither
+        let camera = |x:usize, y:usize| {
+            let offsets = [-10.0, 2.0, 0.0];
+            ray::Ray::new(geo::Point3::new(offsets[x], offsets[y], -5.0), geo::Vec3::new(0.0, 0.0, 2.0))
+        };
+
+        let mut image = canvas::Canvas::new(3, 3);
+        let mut aovs  = Aovs::new(3, 3);
+        let settings  = render::Settings {tile_size:2, ..render::Settings::default()};
+
+        let mask = render(&mut image, &mut aovs, &scene, integrator::Integrator::Phong, &settings, &progress::Silent, camera);
+        assert!(mask.is_complete());
+
+        let at = |a:Aov, x:usize, y:usize| aovs.get(a).pixel_at(x, y);
+        let grey = |v:geo::Float| color::Color::new(v, v, v);
+
+        assert_eq!(at(Aov::Depth, 2, 2), grey(4.0));
+        assert_eq!(at(Aov::Normal, 2, 2), color::Color::new(0.0, 0.0, -1.0));
+        assert_eq!(at(Aov::Albedo, 2, 2), color::Color::new(1.0, 0.0, 0.0));
+        assert_eq!(at(Aov::Id, 2, 2), grey(near_id as geo::Float));
+        assert_eq!(at(Aov::Hits, 2, 2), grey(4.0));
+
+        assert_eq!(at(Aov::Id, 1, 2), grey(far_id as geo::Float));
+        assert_eq!(at(Aov::Hits, 1, 2), grey(2.0));
+
+        assert_eq!(at(Aov::Depth, 0, 0), grey(0.0));
+        assert_eq!(at(Aov::Id, 0, 0), grey(-1.0));
+        assert_eq!(at(Aov::Hits, 0, 0), grey(0.0));
+
+        // with no lights the near ball is black, misses show the environment
+        assert_eq!(image.pixel_at(2, 2), grey(0.0));
+        assert_eq!(image.pixel_at(0, 0), color::Color::new(0.0, 0.0, 1.0));
+
+        let prefix = crate::temp_path("test_aov");
+        aovs.save_all(&prefix);
+        assert!(environment::EnvMap::load(format!("{}_depth.pfm", prefix)).is_ok());
+
+        for a in Aov::ALL.iter() {
+            std::fs::remove_file(format!("{}_{}.pfm", prefix, a.name())).unwrap();
+        }
+
+        // the buffers alone come out the same
+        let mut alone = Aovs::new(3, 3);
+        assert!(render_buffers(&mut alone, &scene, &settings, &progress::Silent, camera).is_complete());
+
+        for a in Aov::ALL.iter() {
+            for (y, x) in (0..3).flat_map(|y| (0..3).map(move |x| (y, x))) {
+                assert_eq!(alone.get(*a).pixel_at(x, y), aovs.get(*a).pixel_at(x, y));
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use crate::geo;
+use crate::ray;
+use crate::simd;
+use crate::matrix;
+
+#[derive(Debug,Copy,Clone)]
+pub struct Aabb {
+    pub min:geo::Point3,
+    pub max:geo::Point3,
+}
+
+impl Aabb {
+
+    pub fn new(min:geo::Point3, max:geo::Point3) -> Aabb {
+        Aabb {min, max}
+    }
+
+    // an empty box contains nothing and is the identity for merge
+    pub fn empty() -> Aabb {
+        Aabb {min:geo::Point3::new( geo::Float::INFINITY,  geo::Float::INFINITY,  geo::Float::INFINITY),
+              max:geo::Point3::new(-geo::Float::INFINITY, -geo::Float::INFINITY, -geo::Float::INFINITY)}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x ||
+        self.min.y > self.max.y ||
+        self.min.z > self.max.z
+    }
+
+    pub fn add_point(&mut self, p:geo::Point3) {
+
+        self.min = geo::Point3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = geo::Point3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+
+    pub fn merge(&self, other:Aabb) -> Aabb {
+
+        let mut ret = *self;
+        ret.add_point(other.min);
+        ret.add_point(other.max);
+        ret
+    }
+
+    pub fn contains_point(&self, p:geo::Point3) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x &&
+        p.y >= self.min.y && p.y <= self.max.y &&
+        p.z >= self.min.z && p.z <= self.max.z
+    }
+
+    pub fn centroid(&self) -> geo::Point3 {
+        geo::Point3::new((self.min.x + self.max.x)*0.5,
+                        (self.min.y + self.max.y)*0.5,
+                        (self.min.z + self.max.z)*0.5)
+    }
+
+    pub fn surface_area(&self) -> geo::Float {
+
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let d = self.max - self.min;
+        2.0*(d.x*d.y + d.y*d.z + d.z*d.x)
+    }
+
+    // axis (0 = x, 1 = y, 2 = z) along which the box is longest
+    pub fn longest_axis(&self) -> usize {
+
+        let d = self.max - self.min;
+
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // bounding box of all eight corners after transformation, so rotated
+    // and sheared boxes stay conservative
+    pub fn transform(&self, m:&matrix::Matrix) -> Aabb {
+
+        if self.is_empty() {
+            return *self;
+        }
+
+        let mut ret = Aabb::empty();
+
+        for &x in [self.min.x, self.max.x].iter() {
+            for &y in [self.min.y, self.max.y].iter() {
+                for &z in [self.min.z, self.max.z].iter() {
+                    ret.add_point(*m*geo::Point3::new(x, y, z));
+                }
+            }
+        }
+
+        ret
+    }
+
+    // slab test, returns the entry and exit distance along the ray
+    pub fn intersect(&self, r:&ray::Ray) -> Option<(geo::Float,geo::Float)> {
+
+        let (xmin, xmax) = Aabb::check_axis(r.orig.x, r.dir.x, self.min.x, self.max.x);
+        let (ymin, ymax) = Aabb::check_axis(r.orig.y, r.dir.y, self.min.y, self.max.y);
+        let (zmin, zmax) = Aabb::check_axis(r.orig.z, r.dir.z, self.min.z, self.max.z);
+
+        let tmin = xmin.max(ymin).max(zmin);
+        let tmax = xmax.min(ymax).min(zmax);
+
+        if tmin > tmax {
+            return None;
+        }
+
+        Some((tmin, tmax))
+    }
+
+    // true if the ray passes through the box at some t in [t0, t1]
+    pub fn hit(&self, r:&ray::Ray, t0:geo::Float, t1:geo::Float) -> bool {
+
+        match self.intersect(r) {
+            Some((tmin, tmax)) => tmax >= t0 && tmin <= t1,
+            None               => false,
+        }
+    }
+
+    // the slab test for the four rays of a packet, each lane agrees with intersect
+    pub fn intersect4(&self, r:&simd::Ray4) -> [Option<(geo::Float,geo::Float)>;4] {
+
+        let slab = |o:simd::F4, d:simd::F4, min:geo::Float, max:geo::Float| {
+            (((simd::F4::splat(min) - o)/d).to_array(), ((simd::F4::splat(max) - o)/d).to_array())
+        };
+
+        let (x0, x1) = slab(r.orig.x, r.dir.x, self.min.x, self.max.x);
+        let (y0, y1) = slab(r.orig.y, r.dir.y, self.min.y, self.max.y);
+        let (z0, z1) = slab(r.orig.z, r.dir.z, self.min.z, self.max.z);
+
+        let lane = |i:usize| {
+
+            let (xmin, xmax) = Aabb::order_axis(x0[i], x1[i]);
+            let (ymin, ymax) = Aabb::order_axis(y0[i], y1[i]);
+            let (zmin, zmax) = Aabb::order_axis(z0[i], z1[i]);
+
+            let tmin = xmin.max(ymin).max(zmin);
+            let tmax = xmax.min(ymax).min(zmax);
+
+            if tmin > tmax { None } else { Some((tmin, tmax)) }
+        };
+
+        [lane(0), lane(1), lane(2), lane(3)]
+    }
+
+    fn check_axis(origin:geo::Float, direction:geo::Float, min:geo::Float, max:geo::Float) -> (geo::Float,geo::Float) {
+
+        let tmin_numerator = min - origin;
+        let tmax_numerator = max - origin;
+
+        // a zero direction component divides to +/- infinity, which is
+        // exactly the behaviour the slab test needs
+        Aabb::order_axis(tmin_numerator/direction, tmax_numerator/direction)
+    }
+
+    fn order_axis(tmin:geo::Float, tmax:geo::Float) -> (geo::Float,geo::Float) {
+
+        if tmin.is_nan() || tmax.is_nan() {
+            // origin lies on a slab plane of a flat box and the ray is parallel to it
+            return (-geo::Float::INFINITY, geo::Float::INFINITY);
+        }
+
+        if tmin > tmax { (tmax, tmin) } else { (tmin, tmax) }
+    }
+}
+
+impl PartialEq for Aabb {
+    fn eq(&self, other: &Self) -> bool {
+        self.min == other.min && self.max == other.max
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::consts::PI;
+
+    #[test]
+    fn empty_box() {
+
+        let b = Aabb::empty();
+
+        assert!(b.is_empty());
+        assert_eq!(b.surface_area(), 0.0);
+    }
+
+    #[test]
+    fn add_points() {
+
+        let mut b = Aabb::empty();
+        b.add_point(geo::Point3::new(-5.0, 2.0, 0.0));
+        b.add_point(geo::Point3::new( 7.0, 0.0,-3.0));
+
+        assert_eq!(b.min, geo::Point3::new(-5.0, 0.0,-3.0));
+        assert_eq!(b.max, geo::Point3::new( 7.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn merge_boxes() {
+
+        let a = Aabb::new(geo::Point3::new(-5.0,-2.0, 0.0), geo::Point3::new(7.0, 4.0, 4.0));
+        let b = Aabb::new(geo::Point3::new( 8.0,-7.0,-2.0), geo::Point3::new(14.0, 2.0, 8.0));
+
+        let m = a.merge(b);
+
+        assert_eq!(m.min, geo::Point3::new(-5.0,-7.0,-2.0));
+        assert_eq!(m.max, geo::Point3::new(14.0, 4.0, 8.0));
+    }
+
+    #[test]
+    fn contains() {
+
+        let b = Aabb::new(geo::Point3::new(5.0,-2.0, 0.0), geo::Point3::new(11.0, 4.0, 7.0));
+
+        assert!( b.contains_point(geo::Point3::new( 5.0,-2.0, 0.0)));
+        assert!( b.contains_point(geo::Point3::new( 8.0, 1.0, 3.0)));
+        assert!(!b.contains_point(geo::Point3::new( 3.0, 0.0, 3.0)));
+        assert!(!b.contains_point(geo::Point3::new( 8.0, 1.0, 8.0)));
+    }
+
+    #[test]
+    fn area_and_centroid() {
+
+        let b = Aabb::new(geo::Point3::new(0.0, 0.0, 0.0), geo::Point3::new(1.0, 2.0, 3.0));
+
+        assert_eq!(b.surface_area(), 22.0);
+        assert_eq!(b.centroid(), geo::Point3::new(0.5, 1.0, 1.5));
+        assert_eq!(b.longest_axis(), 2);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn transformed_box() {
+
+        let b = Aabb::new(geo::Point3::new(-1.0,-1.0,-1.0), geo::Point3::new(1.0, 1.0, 1.0));
+        let m = matrix::Matrix::rotate_x(PI/4.0) * matrix::Matrix::rotate_y(PI/4.0);
+
+        let t = b.transform(&m);
+
+        assert_eq!(t.min, geo::Point3::new(-1.41421,-1.70711,-1.70711));
+        assert_eq!(t.max, geo::Point3::new( 1.41421, 1.70711, 1.70711));
+    }
+
+    #[test]
+    fn ray_hits_box() {
+
+        let b = Aabb::new(geo::Point3::new(-1.0,-1.0,-1.0), geo::Point3::new(1.0, 1.0, 1.0));
+
+        let cases = vec![
+            (geo::Point3::new( 5.0, 0.5, 0.0), geo::Vec3::new(-1.0, 0.0, 0.0), 4.0, 6.0),
+            (geo::Point3::new(-5.0, 0.5, 0.0), geo::Vec3::new( 1.0, 0.0, 0.0), 4.0, 6.0),
+            (geo::Point3::new( 0.5, 5.0, 0.0), geo::Vec3::new( 0.0,-1.0, 0.0), 4.0, 6.0),
+            (geo::Point3::new( 0.5, 0.0,-5.0), geo::Vec3::new( 0.0, 0.0, 1.0), 4.0, 6.0),
+            (geo::Point3::new( 0.0, 0.5, 0.0), geo::Vec3::new( 0.0, 0.0, 1.0),-1.0, 1.0),
+        ];
+
+        for (orig, dir, t0, t1) in cases {
+            let r = ray::Ray::new(orig, dir);
+            assert_eq!(b.intersect(&r), Some((t0, t1)));
+        }
+    }
+
+    #[test]
+    fn ray_misses_box() {
+
+        let b = Aabb::new(geo::Point3::new(-1.0,-1.0,-1.0), geo::Point3::new(1.0, 1.0, 1.0));
+
+        let cases = vec![
+            (geo::Point3::new(-2.0, 0.0, 0.0), geo::Vec3::new(0.2673, 0.5345, 0.8018)),
+            (geo::Point3::new( 0.0,-2.0, 0.0), geo::Vec3::new(0.8018, 0.2673, 0.5345)),
+            (geo::Point3::new( 2.0, 0.0, 2.0), geo::Vec3::new(0.0, 0.0,-1.0)),
+            (geo::Point3::new( 0.0, 2.0, 2.0), geo::Vec3::new(0.0,-1.0, 0.0)),
+        ];
+
+        for (orig, dir) in cases {
+            let r = ray::Ray::new(orig, dir);
+            assert_eq!(b.intersect(&r), None);
+            assert!(!b.hit(&r, 0.0, geo::Float::INFINITY));
+        }
+    }
+
+    #[test]
+    fn box_behind_ray() {
+
+        let b = Aabb::new(geo::Point3::new(-1.0,-1.0,-1.0), geo::Point3::new(1.0, 1.0, 1.0));
+        let r = ray::Ray::new(geo::Point3::new(0.0, 0.0, 5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+
+        assert!(!b.hit(&r, 0.0, geo::Float::INFINITY));
+        assert!( b.hit(&r, -10.0, geo::Float::INFINITY));
+    }
+
+    #[test]
+    fn packet_matches_single_rays() {
+
+        // the last box is flat and the ray slides along its plane
+        let boxes = [Aabb::new(geo::Point3::new(-1.0,-1.0,-1.0), geo::Point3::new(1.0, 1.0, 1.0)),
+                     Aabb::new(geo::Point3::new(-1.0, 0.0,-1.0), geo::Point3::new(1.0, 0.0, 1.0))];
+
+        let rays = [ray::Ray::new(geo::Point3::new( 0.5, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0)),
+                    ray::Ray::new(geo::Point3::new(-2.0, 0.0, 0.0), geo::Vec3::new(0.2673, 0.5345, 0.8018)),
+                    ray::Ray::new(geo::Point3::new( 0.0, 0.0, 5.0), geo::Vec3::new(0.0, 0.0, 1.0)),
+                    ray::Ray::new(geo::Point3::new( 0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0))];
+
+        let packet = simd::Ray4::new(&rays);
+
+        for b in boxes.iter() {
+            let xs = b.intersect4(&packet);
+            for i in 0..4 {
+                assert_eq!(xs[i], b.intersect(&rays[i]));
+            }
+        }
+    }
+}
@@ -0,0 +1,552 @@
+use crate::geo;
+use crate::ray;
+use crate::shape;
+use crate::bounds;
+use crate::packet;
+use std::collections::HashMap;
+
+// leaves are never split below this many shapes
+const MIN_LEAF_SHAPES:usize = 2;
+// and never allowed to grow beyond this many
+const MAX_LEAF_SHAPES:usize = 8;
+// number of buckets the centroids are binned into when evaluating the SAH
+const SAH_BUCKETS:usize = 12;
+// cost of descending into a node relative to testing one shape
+const TRAVERSAL_COST:geo::Float = 0.125;
+
+// per ray counters, accumulate them over a render to profile the tree
+#[derive(Debug,Copy,Clone,Default,PartialEq)]
+pub struct Stats {
+    pub nodes_visited:usize,
+    pub shapes_tested:usize,
+}
+
+#[derive(Debug,Copy,Clone)]
+enum Node {
+    Leaf     { bounds:bounds::Aabb, first:usize, count:usize },
+    Interior { bounds:bounds::Aabb, left:usize,  right:usize },
+}
+
+impl Node {
+
+    fn bounds(&self) -> bounds::Aabb {
+        match self {
+            Node::Leaf     { bounds, .. } => *bounds,
+            Node::Interior { bounds, .. } => *bounds,
+        }
+    }
+}
+
+#[derive(Debug,Clone)]
+pub struct Bvh {
+    nodes:Vec<Node>,
+    shapes:Vec<shape::Shape>,
+    index:HashMap<i32,usize>,
+}
+
+// what the builder needs to know about a shape while partitioning
+#[derive(Debug,Copy,Clone)]
+struct Prim {
+    shape:usize,
+    bounds:bounds::Aabb,
+    centroid:[geo::Float;3],
+}
+
+#[derive(Debug,Copy,Clone)]
+struct Bucket {
+    count:usize,
+    bounds:bounds::Aabb,
+}
+
+impl Bvh {
+
+    pub fn build(shapes:Vec<shape::Shape>) -> Bvh {
+
+        let mut prims:Vec<Prim> = shapes.iter()
+            .enumerate()
+            .map(|(i,s)| {
+                let b = s.bounds();
+                let c = b.centroid();
+                Prim {shape:i, bounds:b, centroid:[c.x, c.y, c.z]}
+            })
+            .collect();
+
+        let mut nodes = Vec::new();
+
+        if !prims.is_empty() {
+            let count = prims.len();
+            Bvh::build_node(&mut nodes, &mut prims, 0, count);
+        }
+
+        // reorder the shapes so every leaf refers to a contiguous range
+        let mut slots:Vec<Option<shape::Shape>> = shapes.into_iter().map(Some).collect();
+        let shapes:Vec<shape::Shape> = prims.iter()
+            .map(|p| slots[p.shape].take().unwrap())
+            .collect();
+
+        // every id in the tree, including children of groups and csg, maps to the
+        // top level shape containing it
+        let mut index = HashMap::new();
+        for (i,s) in shapes.iter().enumerate() {
+            Bvh::index_ids(&mut index, s, i);
+        }
+
+        Bvh {nodes, shapes, index}
+    }
+
+    fn build_node(nodes:&mut Vec<Node>, prims:&mut [Prim], first:usize, count:usize) -> usize {
+
+        let bounds = prims[first..first+count].iter()
+            .fold(bounds::Aabb::empty(), |acc, p| acc.merge(p.bounds));
+
+        let node = nodes.len();
+        nodes.push(Node::Leaf {bounds, first, count});
+
+        if count <= MIN_LEAF_SHAPES {
+            return node;
+        }
+
+        let mut centroids = bounds::Aabb::empty();
+        for p in prims[first..first+count].iter() {
+            centroids.add_point(geo::Point3::new(p.centroid[0], p.centroid[1], p.centroid[2]));
+        }
+
+        let axis = centroids.longest_axis();
+        let lo   = [centroids.min.x, centroids.min.y, centroids.min.z][axis];
+        let hi   = [centroids.max.x, centroids.max.y, centroids.max.z][axis];
+
+        // every centroid in the same spot, no split can separate them
+        if hi - lo <= 0.0 {
+            return node;
+        }
+
+        let bucket_of = |p:&Prim| -> usize {
+            let b = ((p.centroid[axis] - lo)/(hi - lo)*(SAH_BUCKETS as geo::Float)) as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        let mut buckets = [Bucket {count:0, bounds:bounds::Aabb::empty()}; SAH_BUCKETS];
+        for p in prims[first..first+count].iter() {
+            let b = bucket_of(p);
+            buckets[b].count += 1;
+            buckets[b].bounds = buckets[b].bounds.merge(p.bounds);
+        }
+
+        // cost of splitting after each bucket boundary
+        let parent_area = bounds.surface_area();
+        let mut best_cost  = geo::Float::INFINITY;
+        let mut best_split = 0;
+
+        for split in 0..SAH_BUCKETS-1 {
+
+            let (left, right) = buckets.split_at(split + 1);
+
+            let l = left.iter().fold(Bucket {count:0, bounds:bounds::Aabb::empty()}, |acc, b|
+                Bucket {count:acc.count + b.count, bounds:acc.bounds.merge(b.bounds)});
+            let r = right.iter().fold(Bucket {count:0, bounds:bounds::Aabb::empty()}, |acc, b|
+                Bucket {count:acc.count + b.count, bounds:acc.bounds.merge(b.bounds)});
+
+            if l.count == 0 || r.count == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST +
+                       (l.count as geo::Float*l.bounds.surface_area() +
+                        r.count as geo::Float*r.bounds.surface_area())/parent_area;
+
+            if cost < best_cost {
+                best_cost  = cost;
+                best_split = split;
+            }
+        }
+
+        // testing every shape in a leaf costs one unit each
+        let leaf_cost = count as geo::Float;
+        if count <= MAX_LEAF_SHAPES && leaf_cost <= best_cost {
+            return node;
+        }
+
+        let slice = &mut prims[first..first+count];
+        slice.sort_by_key(|p| bucket_of(p) > best_split);
+        let mid = slice.iter().filter(|p| bucket_of(p) <= best_split).count();
+
+        let left  = Bvh::build_node(nodes, prims, first, mid);
+        let right = Bvh::build_node(nodes, prims, first + mid, count - mid);
+
+        nodes[node] = Node::Interior {bounds, left, right};
+
+        node
+    }
+
+    fn index_ids(index:&mut HashMap<i32,usize>, s:&shape::Shape, top:usize) {
+
+        index.insert(s.id(), top);
+
+        for c in s.children() {
+            Bvh::index_ids(index, c, top);
+        }
+    }
+
+    pub fn shapes(&self) -> &[shape::Shape] {
+        &self.shapes
+    }
+
+    pub fn shape(&self, id:i32) -> Option<&shape::Shape> {
+        self.index.get(&id).and_then(|&i| self.shapes[i].find(id))
+    }
+
+    pub fn bounds(&self) -> bounds::Aabb {
+        self.nodes.first().map(|n| n.bounds()).unwrap_or_else(bounds::Aabb::empty)
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn depth(&self) -> usize {
+
+        fn depth_of(nodes:&[Node], n:usize) -> usize {
+            match nodes[n] {
+                Node::Leaf     { .. }             => 1,
+                Node::Interior { left, right, .. } => 1 + depth_of(nodes, left).max(depth_of(nodes, right)),
+            }
+        }
+
+        if self.nodes.is_empty() { 0 } else { depth_of(&self.nodes, 0) }
+    }
+
+    // every intersection along the ray, sorted by t, like Ray::intersect
+    pub fn intersect(&self, r:&ray::Ray, stats:&mut Stats) -> Vec<ray::Isect> {
+
+        let mut xs = Vec::new();
+        let mut stack = Vec::new();
+
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(n) = stack.pop() {
+
+            stats.nodes_visited += 1;
+
+            let node = self.nodes[n];
+            if node.bounds().intersect(r).is_none() {
+                continue;
+            }
+
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    for s in self.shapes[first..first+count].iter() {
+                        stats.shapes_tested += 1;
+                        xs.extend(s.intersect(r));
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+
+        xs.sort();
+        xs
+    }
+
+    // nearest intersection in front of the origin, same contract as ray::Isect::hit,
+    // but subtrees further away than the best hit so far are skipped
+    pub fn hit(&self, r:&ray::Ray, stats:&mut Stats) -> ray::Isect {
+
+        if self.nodes.is_empty() {
+            return ray::Isect::isect(0.0, -1);
+        }
+
+        let best = self.hit_from(0, r, ray::Isect::isect(geo::Float::INFINITY, -1), stats);
+
+        if best.id < 0 {
+            return ray::Isect::isect(0.0, -1);
+        }
+
+        best
+    }
+
+    // the search behind hit, from node root down with best as the hit to beat
+    fn hit_from(&self, root:usize, r:&ray::Ray, mut best:ray::Isect, stats:&mut Stats) -> ray::Isect {
+
+        let mut stack = vec![root];
+
+        while let Some(n) = stack.pop() {
+
+            stats.nodes_visited += 1;
+
+            let node = self.nodes[n];
+            if !node.bounds().hit(r, 0.0, best.t) {
+                continue;
+            }
+
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    for s in self.shapes[first..first+count].iter() {
+                        stats.shapes_tested += 1;
+                        let h = ray::Isect::hit(s.intersect(r));
+                        if h.id >= 0 && h.t < best.t {
+                            best = h;
+                        }
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    // visit the nearer child first so the far one is more likely culled
+                    let near = |c:usize| self.nodes[c].bounds().intersect(r).map(|(t,_)| t).unwrap_or(geo::Float::INFINITY);
+                    if near(left) <= near(right) {
+                        stack.push(right);
+                        stack.push(left);
+                    } else {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    // hit for each ray of a packet. The four rays walk the tree together while
+    // at least two of them still reach a node, once only one does it carries on
+    // through that subtree on its own.
+    pub fn hit_packet(&self, p:&packet::Packet, stats:&mut Stats) -> [ray::Isect;4] {
+
+        let mut best  = [ray::Isect::isect(geo::Float::INFINITY, -1);4];
+        let mut stack = Vec::new();
+
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(n) = stack.pop() {
+
+            stats.nodes_visited += 1;
+
+            let node  = self.nodes[n];
+            let boxes = node.bounds().intersect4(p.lanes());
+
+            let active:Vec<usize> = (0..4)
+                .filter(|&i| match boxes[i] {
+                    Some((tmin, tmax)) => tmax >= 0.0 && tmin <= best[i].t,
+                    None               => false,
+                })
+                .collect();
+
+            if active.is_empty() {
+                continue;
+            }
+
+            if active.len() == 1 {
+                let i = active[0];
+                // hit_from counts this node again
+                stats.nodes_visited -= 1;
+                best[i] = self.hit_from(n, &p.rays()[i], best[i], stats);
+                continue;
+            }
+
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    for s in self.shapes[first..first+count].iter() {
+                        stats.shapes_tested += 1;
+                        let hs = p.hit(s);
+                        for &i in active.iter() {
+                            if hs[i].id >= 0 && hs[i].t < best[i].t {
+                                best[i] = hs[i];
+                            }
+                        }
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    // order the children by how close they are to the first active ray
+                    let r = &p.rays()[active[0]];
+                    let near = |c:usize| self.nodes[c].bounds().intersect(r).map(|(t,_)| t).unwrap_or(geo::Float::INFINITY);
+                    if near(left) <= near(right) {
+                        stack.push(right);
+                        stack.push(left);
+                    } else {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+            }
+        }
+
+        for b in best.iter_mut() {
+            if b.id < 0 {
+                *b = ray::Isect::isect(0.0, -1);
+            }
+        }
+
+        best
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group;
+    use crate::matrix;
+
+    fn sphere_at(x:geo::Float, y:geo::Float, z:geo::Float) -> shape::Shape {
+        let mut s = ray::Sphere::unit();
+        s.set_transform(matrix::Matrix::translate(x, y, z)).unwrap();
+        shape::Shape::from(s)
+    }
+
+    fn row_of_spheres(n:usize) -> Vec<shape::Shape> {
+        (0..n).map(|i| sphere_at(3.0*i as geo::Float, 0.0, 0.0)).collect()
+    }
+
+    #[test]
+    fn empty_tree() {
+
+        let bvh = Bvh::build(vec![]);
+        let r   = ray::Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let mut stats = Stats::default();
+
+        assert_eq!(bvh.node_count(), 0);
+        assert!(bvh.intersect(&r, &mut stats).is_empty());
+        assert_eq!(bvh.hit(&r, &mut stats).id, -1);
+    }
+
+    #[test]
+    fn tree_bounds_every_shape() {
+
+        let bvh = Bvh::build(row_of_spheres(20));
+        let b   = bvh.bounds();
+
+        assert_eq!(b.min, geo::Point3::new(-1.0,-1.0,-1.0));
+        assert_eq!(b.max, geo::Point3::new(58.0, 1.0, 1.0));
+        assert_eq!(bvh.shapes().len(), 20);
+        assert!(bvh.depth() > 1);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+
+        let shapes = row_of_spheres(40);
+        let bvh    = Bvh::build(shapes.clone());
+
+        for i in 0..40 {
+
+            let r = ray::Ray::new(geo::Point3::new(3.0*i as geo::Float + 0.3, 0.2,-5.0),
+                                  geo::Vec3::new(0.0, 0.0, 1.0));
+
+            let mut expected:Vec<ray::Isect> = shapes.iter().flat_map(|s| s.intersect(&r)).collect();
+            expected.sort();
+
+            let mut stats = Stats::default();
+            assert_eq!(bvh.intersect(&r, &mut stats), expected);
+            assert_eq!(bvh.hit(&r, &mut stats), ray::Isect::hit(expected));
+        }
+    }
+
+    #[test]
+    fn finds_shape_by_id() {
+
+        let shapes = row_of_spheres(10);
+        let id     = shapes[7].id();
+        let bvh    = Bvh::build(shapes);
+
+        assert_eq!(bvh.shape(id).unwrap().id(), id);
+        assert!(bvh.shape(-1).is_none());
+    }
+
+    #[test]
+    fn finds_children_of_groups() {
+
+        let s  = ray::Sphere::unit();
+        let id = s.id();
+
+        let mut g = group::Group::new();
+        g.set_transform(matrix::Matrix::translate(10.0, 0.0, 0.0)).unwrap();
+        g.add_child(s);
+
+        let mut shapes = row_of_spheres(5);
+        shapes.push(shape::Shape::from(g));
+
+        let bvh = Bvh::build(shapes);
+        let r   = ray::Ray::new(geo::Point3::new(10.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let mut stats = Stats::default();
+
+        let hit = bvh.hit(&r, &mut stats);
+
+        assert_eq!(hit.id, id);
+        assert_eq!(bvh.shape(id).unwrap().normal_at(r.position(hit.t)), Some(geo::Vec3::new(0.0, 0.0,-1.0)));
+    }
+
+    #[test]
+    fn rejects_rays_at_the_root() {
+
+        let bvh = Bvh::build(row_of_spheres(40));
+        let r   = ray::Ray::new(geo::Point3::new(0.0, 10.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let mut stats = Stats::default();
+
+        assert!(bvh.intersect(&r, &mut stats).is_empty());
+        assert_eq!(stats.nodes_visited, 1);
+        assert_eq!(stats.shapes_tested, 0);
+    }
+
+    #[test]
+    fn visits_fewer_nodes_than_shapes() {
+
+        let bvh = Bvh::build(row_of_spheres(64));
+        let r   = ray::Ray::new(geo::Point3::new(30.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let mut stats = Stats::default();
+
+        let hit = bvh.hit(&r, &mut stats);
+
+        assert_eq!(hit.t, 4.0);
+        assert!(stats.shapes_tested < 64/4);
+        assert!(stats.nodes_visited < bvh.node_count());
+    }
+
+    fn packet_at(x:geo::Float, spread:geo::Float) -> packet::Packet {
+
+        let r = |dx:geo::Float, dy:geo::Float| ray::Ray::new(geo::Point3::new(x + dx, dy,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+
+        packet::Packet::new([r(0.0, 0.0), r(spread, 0.0), r(0.0,-0.2), r(spread,-0.2)])
+    }
+
+    #[test]
+    fn packet_matches_single_rays() {
+
+        let bvh = Bvh::build(row_of_spheres(40));
+
+        // a tight packet, one that straddles a gap and one spread over the whole row
+        for p in [packet_at(30.3, 0.2), packet_at(31.5, 1.0), packet_at(0.0, 40.0)].iter() {
+
+            let mut stats = Stats::default();
+            let hs = bvh.hit_packet(p, &mut stats);
+
+            for (h, r) in hs.iter().zip(p.rays().iter()) {
+                assert_eq!(*h, bvh.hit(r, &mut stats));
+            }
+        }
+
+        let mut stats = Stats::default();
+        assert_eq!(Bvh::build(vec![]).hit_packet(&packet_at(0.0, 0.2), &mut stats)[0].id, -1);
+    }
+
+    #[test]
+    fn coherent_packet_shares_traversal() {
+
+        let bvh = Bvh::build(row_of_spheres(64));
+        let p   = packet_at(30.3, 0.2);
+
+        let mut single = Stats::default();
+        for r in p.rays().iter() {
+            bvh.hit(r, &mut single);
+        }
+
+        let mut packed = Stats::default();
+        bvh.hit_packet(&p, &mut packed);
+
+        assert!(packed.nodes_visited*2 < single.nodes_visited);
+    }
+}
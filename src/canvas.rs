@@ -1,3 +1,4 @@
+#[cfg(test)]
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
@@ -13,12 +14,41 @@ pub struct Canvas {
     grid: Vec<color::Color>
 }
 
+// a rectangle of a canvas that borrows its own rows, so tiles can be written
+// from different threads at once. Coordinates are those of the whole canvas.
+pub struct Tile<'a> {
+    pub x:usize,
+    pub y:usize,
+    pub width:usize,
+    pub height:usize,
+    // rays traced for this tile, for progress reporting
+    pub rays:usize,
+    rows:Vec<&'a mut [color::Color]>,
+}
+
+impl<'a> Tile<'a> {
+
+    pub fn pixel_at(&self,x:usize,y:usize) -> color::Color {
+
+        self.rows[y - self.y][x - self.x]
+    }
+
+    pub fn write_pixel(&mut self,x:usize,y:usize,c:color::Color) {
+
+        if x >= self.x && x < self.x + self.width &&
+           y >= self.y && y < self.y + self.height {
+
+            self.rows[y - self.y][x - self.x] = c;
+        }
+    }
+}
+
 impl Canvas {
 
     pub fn new(width:usize, height:usize) -> Canvas {
-        let grid = vec!( color::Color::new(0.0,0.0,0.0) ; (width*height) as usize );
+        let grid = vec!( color::Color::new(0.0,0.0,0.0) ; width*height );
 
-        Canvas {width:width, height:height, grid:grid}
+        Canvas {width, height, grid}
     }
 
     pub fn get_width(&self) -> usize {
@@ -43,11 +73,68 @@ impl Canvas {
         }
     }
 
+    // splits the canvas into size x size tiles, row by row, the last row and
+    // column of tiles are cut short when size does not divide the canvas
+    pub fn tiles(&mut self, size:usize) -> Vec<Tile<'_>> {
+
+        assert!(size > 0, "tile size must be at least one pixel");
+
+        let width = self.width;
+        let mut tiles = Vec::new();
+
+        if self.grid.is_empty() {
+            return tiles;
+        }
+
+        for (band, rows) in self.grid.chunks_mut(width*size).enumerate() {
+
+            let mut band_tiles:Vec<Tile> = (0..width).step_by(size)
+                .map(|x| Tile {x, y:band*size, width:size.min(width - x), height:rows.len()/width, rays:0, rows:Vec::new()})
+                .collect();
+
+            for row in rows.chunks_mut(width) {
+
+                let mut rest = row;
+
+                for t in band_tiles.iter_mut() {
+                    let (head, tail) = std::mem::take(&mut rest).split_at_mut(t.width);
+                    t.rows.push(head);
+                    rest = tail;
+                }
+            }
+
+            tiles.extend(band_tiles);
+        }
+
+        tiles
+    }
+
+    // the exact values as a little endian colour pfm, for buffers that do not
+    // fit in 0..1 like depth or normals
+    // the cast only does something without the f32 feature
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_pfm(&self, path:&str) {
+
+        let mut bytes = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+
+        // bottom row first
+        for row in self.grid.chunks(self.width.max(1)).rev() {
+            for c in row.iter() {
+                for v in [c.red, c.green, c.blue].iter() {
+                    bytes.extend_from_slice(&(*v as f32).to_le_bytes());
+                }
+            }
+        }
+
+        File::create(Path::new(path)).expect("failed to create")
+            .write_all(&bytes).expect("failed to write");
+    }
+
     pub fn to_ppm(&self, path:&str) {
 
 	let path = Path::new(path);
 
-	let file = File::create(&path).expect("failed to create");
+	let file = File::create(path).expect("failed to create");
 
         let mut file = LineWriter::new(file);
 
@@ -86,6 +173,7 @@ impl Canvas {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::geo;
 
     #[test]
     fn empty_canvas () {
@@ -184,4 +272,71 @@ mod tests {
         255 204 153 255 204 153 255 204 153 255 204 153 255 204 153")
     
     }
-} 
+
+    #[test]
+    fn tiles_cover_canvas_once() {
+
+        let mut c = Canvas::new(10,7);
+
+        {
+            let tiles = c.tiles(4);
+
+            assert_eq!(tiles.len(), 3*2);
+            assert_eq!((tiles[2].x, tiles[2].y, tiles[2].width, tiles[2].height), (8, 0, 2, 4));
+            assert_eq!((tiles[5].x, tiles[5].y, tiles[5].width, tiles[5].height), (8, 4, 2, 3));
+
+            for mut t in tiles {
+                for y in t.y..t.y+t.height {
+                    for x in t.x..t.x+t.width {
+                        let old = t.pixel_at(x,y);
+                        t.write_pixel(x,y,old + color::Color::new(x as geo::Float, y as geo::Float, 1.0));
+                    }
+                }
+            }
+        }
+
+        for x in 0..10 {
+            for y in 0..7 {
+                assert_eq!(c.pixel_at(x,y), color::Color::new(x as geo::Float, y as geo::Float, 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn tile_ignores_writes_outside() {
+
+        let mut c = Canvas::new(4,4);
+        let red = color::Color::new(1.0,0.0,0.0);
+
+        {
+            let mut tiles = c.tiles(2);
+            tiles[0].write_pixel(2,0,red);
+            tiles[3].write_pixel(3,3,red);
+        }
+
+        assert_eq!(c.pixel_at(2,0), color::Color::new(0.0,0.0,0.0));
+        assert_eq!(c.pixel_at(3,3), red);
+    }
+
+    #[test]
+    fn pfm_conversion() {
+
+        let mut c = Canvas::new(2,2);
+        c.write_pixel(0,1,color::Color::new(-1.5,2.0,100.0));
+        let path = crate::temp_path("test1.pfm");
+        c.to_pfm(&path);
+
+        let bytes  = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        let value  = |i:usize| f32::from_le_bytes([bytes[header.len() + 4*i], bytes[header.len() + 4*i + 1],
+                                                   bytes[header.len() + 4*i + 2], bytes[header.len() + 4*i + 3]]);
+
+        assert!(bytes.starts_with(header));
+        assert_eq!(bytes.len(), header.len() + 2*2*3*4);
+        // the bottom row comes first
+        assert_eq!((value(0), value(1), value(2)), (-1.5, 2.0, 100.0));
+        assert_eq!(value(3), 0.0);
+    }
+}
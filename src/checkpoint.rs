@@ -0,0 +1,389 @@
+use crate::geo;
+use crate::color;
+use crate::render;
+use crate::progressive;
+use crate::integrator;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self,File};
+use std::io::{self,BufReader,BufWriter,Read,Write};
+use std::path::{Path,PathBuf};
+use std::time::{Duration,Instant};
+
+// File layout, all integers little endian:
+//
+//     magic      8 bytes  "RTCHKPT\0"
+//     version    u32
+//     float size u8       4 or 8, the precision the sums were written at
+//     width      u32
+//     height     u32
+//     tile size  u32
+//     samples    u32      samples per pixel the render is aiming for
+//     seed       u64      the render's random seed
+//     mode       u8       0 Phong, 1 path tracing, 2 ambient occlusion
+//     depth      u32      path depth or occlusion rays, 0 for Phong
+//     distance   float    occlusion max distance, 0 otherwise
+//     env        u64      Environment::fingerprint of what surrounds the scene
+//     then per pixel, row by row:
+//     count      u32
+//     red, green, blue    floats of the size above
+//
+// Sums are stored bit for bit, so a resumed render adds its samples to exactly
+// the values an uninterrupted one would have had.
+const MAGIC:&[u8;8] = b"RTCHKPT\0";
+const VERSION:u32 = 1;
+const FLOAT_SIZE:u8 = std::mem::size_of::<geo::Float>() as u8;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    // the file is not a checkpoint this build can read, or the render could
+    // not be written as one
+    Format(&'static str),
+    // a good checkpoint, but of a different render than the one asked for
+    Mismatch(&'static str),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e)       => write!(f, "checkpoint i/o failed: {}", e),
+            CheckpointError::Format(m)   => write!(f, "bad checkpoint: {}", m),
+            CheckpointError::Mismatch(m) => write!(f, "checkpoint is of another render: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e:io::Error) -> CheckpointError {
+        CheckpointError::Io(e)
+    }
+}
+
+// everything needed to carry on a progressive render
+#[derive(Debug,Clone)]
+pub struct Checkpoint {
+    pub samples:u32,
+    pub tile_size:usize,
+    pub seed:u64,
+    pub integrator:integrator::Integrator,
+    // Environment::fingerprint of the render's environment
+    pub environment:u64,
+    pub acc:progressive::Accumulator,
+}
+
+impl Checkpoint {
+
+    // the settings to resume with, the time limit and thread count are up to
+    // whoever resumes
+    pub fn settings(&self) -> render::Settings {
+        render::Settings {tile_size:self.tile_size, seed:self.seed, ..render::Settings::default()}
+    }
+
+    // Ok if this can be carried on as a render of that size, integrator and
+    // environment fingerprint. Anything else would average samples of two
+    // different images.
+    pub fn check(&self, width:usize, height:usize, integrator:integrator::Integrator, environment:u64)
+        -> Result<(), CheckpointError> {
+
+        if self.acc.get_width() != width || self.acc.get_height() != height {
+            return Err(CheckpointError::Mismatch("made for a different image size"));
+        }
+        if self.integrator != integrator {
+            return Err(CheckpointError::Mismatch("made with a different render mode"));
+        }
+        if self.environment != environment {
+            return Err(CheckpointError::Mismatch("made with a different environment"));
+        }
+
+        Ok(())
+    }
+
+    // written to a temporary file first and renamed over path, so a crash
+    // while saving leaves the previous checkpoint intact
+    pub fn save<P:AsRef<Path>>(&self, path:P) -> Result<(), CheckpointError> {
+
+        let path = path.as_ref();
+        let tmp  = temp_path(path);
+
+        {
+            let mut w = BufWriter::new(File::create(&tmp)?);
+            let acc   = &self.acc;
+
+            w.write_all(MAGIC)?;
+            w.write_all(&VERSION.to_le_bytes())?;
+            w.write_all(&[FLOAT_SIZE])?;
+
+            for v in [acc.get_width(), acc.get_height(), self.tile_size].iter() {
+                let v = u32::try_from(*v).map_err(|_| CheckpointError::Format("image or tile too large to save"))?;
+                w.write_all(&v.to_le_bytes())?;
+            }
+
+            w.write_all(&self.samples.to_le_bytes())?;
+            w.write_all(&self.seed.to_le_bytes())?;
+
+            let (mode, depth, distance) = match self.integrator {
+                integrator::Integrator::Phong                          => (0u8, 0, 0.0),
+                integrator::Integrator::Path {max_depth}               => (1, max_depth, 0.0),
+                integrator::Integrator::Occlusion {rays, max_distance} => (2, rays, max_distance),
+            };
+
+            w.write_all(&[mode])?;
+            w.write_all(&depth.to_le_bytes())?;
+            w.write_all(&distance.to_le_bytes())?;
+            w.write_all(&self.environment.to_le_bytes())?;
+
+            for y in 0..acc.get_height() {
+                for x in 0..acc.get_width() {
+                    let s = acc.sum_at(x, y);
+                    w.write_all(&acc.samples_at(x, y).to_le_bytes())?;
+                    w.write_all(&s.red.to_le_bytes())?;
+                    w.write_all(&s.green.to_le_bytes())?;
+                    w.write_all(&s.blue.to_le_bytes())?;
+                }
+            }
+
+            w.flush()?;
+        }
+
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load<P:AsRef<Path>>(path:P) -> Result<Checkpoint, CheckpointError> {
+
+        let mut r = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8;8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(CheckpointError::Format("not a checkpoint file"));
+        }
+
+        if read_u32(&mut r)? != VERSION {
+            return Err(CheckpointError::Format("unsupported version"));
+        }
+
+        let mut size = [0u8;1];
+        r.read_exact(&mut size)?;
+        if size[0] != FLOAT_SIZE {
+            return Err(CheckpointError::Format("written at a different float precision"));
+        }
+
+        let width     = read_u32(&mut r)? as usize;
+        let height    = read_u32(&mut r)? as usize;
+        let tile_size = read_u32(&mut r)? as usize;
+        let samples   = read_u32(&mut r)?;
+        let seed      = read_u64(&mut r)?;
+
+        if tile_size == 0 {
+            return Err(CheckpointError::Format("zero tile size"));
+        }
+
+        let mut mode = [0u8;1];
+        r.read_exact(&mut mode)?;
+        let depth    = read_u32(&mut r)?;
+        let distance = read_float(&mut r)?;
+
+        let integrator = match mode[0] {
+            0 => integrator::Integrator::Phong,
+            1 => integrator::Integrator::Path {max_depth:depth},
+            2 => integrator::Integrator::Occlusion {rays:depth, max_distance:distance},
+            _ => return Err(CheckpointError::Format("unknown render mode")),
+        };
+
+        let environment = read_u64(&mut r)?;
+
+        // not preallocated, a corrupt header should end in an error at the end
+        // of the file rather than a huge allocation
+        let mut sums   = Vec::new();
+        let mut counts = Vec::new();
+
+        for _ in 0..width*height {
+            counts.push(read_u32(&mut r)?);
+            sums.push(color::Color::new(read_float(&mut r)?, read_float(&mut r)?, read_float(&mut r)?));
+        }
+
+        let acc = progressive::Accumulator::from_parts(width, height, sums, counts)
+            .ok_or(CheckpointError::Format("pixel data does not match the size"))?;
+
+        Ok(Checkpoint {samples, tile_size, seed, integrator, environment, acc})
+    }
+}
+
+// an after_pass callback for progressive::render that saves a checkpoint at
+// most once per interval, and always once the last sample is in. A failed save
+// is reported and the render carries on. environment is the fingerprint of the
+// render's environment.
+pub fn saver<P:AsRef<Path>>(path:P, interval:Duration, samples:u32, settings:&render::Settings,
+                            integrator:integrator::Integrator, environment:u64)
+    -> impl FnMut(&progressive::Accumulator, u32) {
+
+    let path = path.as_ref().to_path_buf();
+    let (tile_size, seed) = (settings.tile_size, settings.seed);
+    let mut last = Instant::now();
+
+    move |acc, _| {
+
+        if last.elapsed() < interval && acc.min_samples() < samples {
+            return;
+        }
+
+        let c = Checkpoint {samples, tile_size, seed, integrator, environment, acc:acc.clone()};
+
+        if let Err(e) = c.save(&path) {
+            eprintln!("{}: {}", path.display(), e);
+        }
+
+        last = Instant::now();
+    }
+}
+
+fn temp_path(path:&Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+fn read_u32<R:Read>(r:&mut R) -> io::Result<u32> {
+    let mut b = [0u8;4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64<R:Read>(r:&mut R) -> io::Result<u64> {
+    let mut b = [0u8;8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn read_float<R:Read>(r:&mut R) -> io::Result<geo::Float> {
+    let mut b = [0u8;std::mem::size_of::<geo::Float>()];
+    r.read_exact(&mut b)?;
+    Ok(geo::Float::from_le_bytes(b))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas;
+    use crate::progress;
+    use crate::rng;
+
+    const SEED:u64 = 0x5eed;
+    const ENV:u64  = 0xe4f;
+
+    // a noisy sampler drawing from the per sample generators
+    fn noisy(t:&mut canvas::Tile, sample:u32) {
+        for y in t.y..t.y + t.height {
+            for x in t.x..t.x + t.width {
+                let mut rng = rng::Pcg32::for_sample(SEED, x, y, sample);
+                let h = rng.next_float();
+                t.write_pixel(x, y, color::Color::new(h, 1.0/(1.0 + h), rng.next_float()));
+            }
+        }
+    }
+
+    fn same_bits(a:&progressive::Accumulator, b:&progressive::Accumulator) -> bool {
+
+        (0..a.get_height()).all(|y| (0..a.get_width()).all(|x| {
+            let (sa, sb) = (a.sum_at(x, y), b.sum_at(x, y));
+            a.samples_at(x, y) == b.samples_at(x, y) &&
+            sa.red.to_bits() == sb.red.to_bits() &&
+            sa.green.to_bits() == sb.green.to_bits() &&
+            sa.blue.to_bits() == sb.blue.to_bits()
+        }))
+    }
+
+    #[test]
+    fn round_trip() {
+
+        let mut acc = progressive::Accumulator::new(5, 3);
+        progressive::render(&mut acc, 3, &render::Settings::default(), &progress::Silent, noisy, |_, _| {});
+
+        let occlusion = integrator::Integrator::Occlusion {rays:8, max_distance:1.5};
+        let c = Checkpoint {samples:10, tile_size:16, seed:SEED, integrator:occlusion, environment:ENV, acc};
+        let path = crate::temp_path("round_trip.ckpt");
+        c.save(&path).unwrap();
+
+        let l = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(l.samples, 10);
+        assert_eq!(l.settings().tile_size, 16);
+        assert_eq!(l.settings().seed, SEED);
+        assert_eq!(l.integrator, occlusion);
+        assert_eq!(l.environment, ENV);
+        assert!(same_bits(&l.acc, &c.acc));
+    }
+
+    #[test]
+    fn refuses_another_render() {
+
+        let traced = integrator::Integrator::Path {max_depth:4};
+        let c = Checkpoint {samples:1, tile_size:16, seed:SEED, integrator:traced, environment:ENV,
+                            acc:progressive::Accumulator::new(5, 3)};
+
+        assert!(c.check(5, 3, traced, ENV).is_ok());
+
+        let refused = |r:Result<(), CheckpointError>| matches!(r, Err(CheckpointError::Mismatch(_)));
+
+        assert!(refused(c.check(5, 4, traced, ENV)));
+        assert!(refused(c.check(5, 3, integrator::Integrator::Path {max_depth:5}, ENV)));
+        assert!(refused(c.check(5, 3, integrator::Integrator::Phong, ENV)));
+        assert!(refused(c.check(5, 3, traced, ENV + 1)));
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted() {
+
+        let settings = render::Settings {tile_size:4, threads:1, seed:SEED, ..render::Settings::default()};
+
+        let mut straight = progressive::Accumulator::new(10, 6);
+        progressive::render(&mut straight, 5, &settings, &progress::Silent, noisy, |_, _| {});
+
+        // cancelled part way through the third pass, saving after every pass
+        let mut acc = progressive::Accumulator::new(10, 6);
+        let cancel  = settings.cancel.clone();
+        let path    = crate::temp_path("resume.ckpt");
+        let save    = saver(&path, Duration::from_secs(0), 5, &settings, integrator::Integrator::Phong, ENV);
+
+        progressive::render(&mut acc, 5, &settings, &progress::Silent, |t, s| {
+            if s == 2 && t.x == 4 {
+                cancel.cancel();
+            }
+            noisy(t, s)
+        }, save);
+
+        let mut c = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(c.acc.min_samples(), 2);
+        assert_eq!(c.acc.samples_at(0, 0), 3);
+
+        let resume = c.settings();
+        progressive::render(&mut c.acc, c.samples, &resume, &progress::Silent, noisy, |_, _| {});
+
+        assert!(same_bits(&c.acc, &straight));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+
+        let path = crate::temp_path("not.ckpt");
+        fs::write(&path, b"P3\n1 1\n255\n0 0 0\n").unwrap();
+
+        let loaded = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        match loaded {
+            Err(CheckpointError::Format(_)) => {}
+            other => panic!("expected a format error, got {:?}", other),
+        }
+
+        assert!(matches!(Checkpoint::load(crate::temp_path("missing.ckpt")), Err(CheckpointError::Io(_))));
+    }
+}
@@ -1,37 +1,49 @@
 use std::ops;
+use crate::geo;
 
-const EPSILON:f64 = 0.0000001;
+// colours are compared more tightly than geometry where the precision allows
+#[cfg(not(feature = "f32"))]
+const EPSILON:geo::Float = 0.0000001;
+#[cfg(feature = "f32")]
+const EPSILON:geo::Float = 0.0001;
 
 #[derive(Debug,Copy,Clone)]
 pub struct Color {
-    pub red   : f64,
-    pub green : f64,
-    pub blue  : f64 
+    pub red   : geo::Float,
+    pub green : geo::Float,
+    pub blue  : geo::Float 
 }
 
 impl Color {
 
-    pub fn new(red:f64, green:f64, blue:f64) -> Color {
-        Color { red:red, green:green, blue:blue }
+    pub fn new(red:geo::Float, green:geo::Float, blue:geo::Float) -> Color {
+        Color { red, green, blue }
     }
 
-    fn scale_color(color_value:f64, scale_factor:i32) -> i32 {
+    // perceived brightness, with the Rec. 709 weights
+    pub fn luminance(&self) -> geo::Float {
+        0.2126*self.red + 0.7152*self.green + 0.0722*self.blue
+    }
+
+    fn scale_color(color_value:geo::Float, scale_factor:i32) -> i32 {
         
         if color_value >= 1.0 {
             
-            return scale_factor
+            scale_factor
 
         } else if color_value <= 0.0 {
 
-            return 0;
+            0
 
         } else {
 
-            let sf = scale_factor as f64;
-            return (color_value * sf) as i32;
+            let sf = scale_factor as geo::Float;
+            (color_value * sf) as i32
         }
     }
 
+    // by reference as it always has been, for existing callers
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_ppm(&self, sf:i32) -> String {
 
         format!("{} {} {} " ,Color::scale_color(self.red,   sf),
@@ -75,7 +87,7 @@ impl ops::Mul for Color {
 
 
 
-impl ops::Mul<Color> for f64 {
+impl ops::Mul<Color> for geo::Float {
     type Output = Color;
 
     fn mul(self,rhs:Color) -> Color {
@@ -85,10 +97,10 @@ impl ops::Mul<Color> for f64 {
     }
 }
 
-impl ops::Mul<f64> for Color {
+impl ops::Mul<geo::Float> for Color {
     type Output = Color;
 
-    fn mul(self,rhs:f64) -> Color {
+    fn mul(self,rhs:geo::Float) -> Color {
         Color::new(self.red * rhs,
                  self.green * rhs,
                  self.blue * rhs)
@@ -0,0 +1,292 @@
+use crate::ray;
+use crate::shape;
+use crate::matrix;
+use crate::bounds;
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+// constructive solid geometry, a boolean operation between two shapes which
+// may be primitives, groups or further csg shapes
+#[derive(Debug,Clone)]
+pub struct Csg {
+    id:i32,
+    op:Operation,
+    transform:matrix::Transform,
+    // the enclosing groups' transforms composed into one
+    parent:matrix::Transform,
+    left:Box<shape::Shape>,
+    right:Box<shape::Shape>,
+}
+
+impl Csg {
+
+    pub fn new<L:Into<shape::Shape>, R:Into<shape::Shape>>(op:Operation, left:L, right:R) -> Csg {
+
+        let mut csg = Csg {id:ray::next_shape_id(),
+                           op,
+                           transform:matrix::Transform::identity(),
+                           parent:matrix::Transform::identity(),
+                           left:Box::new(left.into()),
+                           right:Box::new(right.into())};
+
+        csg.update_children();
+        csg
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn op(&self) -> Operation {
+        self.op
+    }
+
+    pub fn transform(&self) -> matrix::Matrix {
+        self.transform.matrix()
+    }
+
+    pub fn left(&self) -> &shape::Shape {
+        &self.left
+    }
+
+    pub fn right(&self) -> &shape::Shape {
+        &self.right
+    }
+
+    pub fn set_transform(&mut self, m:matrix::Matrix) -> Result<(), matrix::TransformError> {
+
+        self.transform = matrix::Transform::new(m)
+            .ok_or_else(|| matrix::TransformError::new("csg", self.id, m))?;
+
+        self.update_children();
+        Ok(())
+    }
+
+    pub fn set_parent(&mut self, parent:matrix::Transform) {
+        self.parent = parent;
+        self.update_children();
+    }
+
+    fn update_children(&mut self) {
+
+        let parent = self.parent.compose(&self.transform);
+
+        self.left.set_parent(parent);
+        self.right.set_parent(parent);
+    }
+
+    // bounds in the parent's space, the union of both operands is always
+    // conservative whatever the operation removes
+    pub fn bounds(&self) -> bounds::Aabb {
+        self.left.bounds().merge(self.right.bounds()).transform(&self.transform.matrix())
+    }
+
+    // lhit: the left operand was hit, inl/inr: the ray is currently inside
+    // the left/right operand
+    pub fn intersection_allowed(op:Operation, lhit:bool, inl:bool, inr:bool) -> bool {
+        match op {
+            Operation::Union        => (lhit && !inr) || (!lhit && !inl),
+            Operation::Intersection => (lhit &&  inr) || (!lhit &&  inl),
+            Operation::Difference   => (lhit && !inr) || (!lhit &&  inl),
+        }
+    }
+
+    // walks the sorted intersections of both operands, each paired with
+    // whether it is on the left one, tracking whether the ray is inside each
+    // operand, and keeps those on the combined surface
+    pub fn filter_intersections(&self, xs:Vec<(ray::Isect, bool)>) -> Vec<ray::Isect> {
+
+        let mut inl = false;
+        let mut inr = false;
+
+        let mut ret = Vec::new();
+
+        for (i, lhit) in xs {
+
+            if Csg::intersection_allowed(self.op, lhit, inl, inr) {
+                ret.push(i);
+            }
+
+            if lhit {
+                inl = !inl;
+            } else {
+                inr = !inr;
+            }
+        }
+
+        ret
+    }
+
+    pub fn intersect(&self, r:&ray::Ray) -> Vec<ray::Isect> {
+
+        let r = r.transform(self.transform.inverse());
+
+        // each intersection is tagged with its side as it is found
+        let mut xs:Vec<(ray::Isect, bool)> = self.left.intersect(&r).into_iter().map(|i| (i, true)).collect();
+        xs.extend(self.right.intersect(&r).into_iter().map(|i| (i, false)));
+        xs.sort_by_key(|x| x.0);
+
+        self.filter_intersections(xs)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo;
+    use crate::group;
+
+    #[test]
+    fn new_csg() {
+
+        let s1 = ray::Sphere::unit();
+        let s2 = ray::Sphere::unit();
+        let (id1, id2) = (s1.id(), s2.id());
+
+        let c = Csg::new(Operation::Union, s1, s2);
+
+        assert_eq!(c.op(), Operation::Union);
+        assert_eq!(c.left().id(), id1);
+        assert_eq!(c.right().id(), id2);
+    }
+
+    #[test]
+    fn rules() {
+
+        // op, lhit, inl, inr, allowed
+        let cases = vec![
+            (Operation::Union,        true,  true,  true,  false),
+            (Operation::Union,        true,  true,  false, true ),
+            (Operation::Union,        true,  false, true,  false),
+            (Operation::Union,        true,  false, false, true ),
+            (Operation::Union,        false, true,  true,  false),
+            (Operation::Union,        false, true,  false, false),
+            (Operation::Union,        false, false, true,  true ),
+            (Operation::Union,        false, false, false, true ),
+            (Operation::Intersection, true,  true,  true,  true ),
+            (Operation::Intersection, true,  true,  false, false),
+            (Operation::Intersection, true,  false, true,  true ),
+            (Operation::Intersection, true,  false, false, false),
+            (Operation::Intersection, false, true,  true,  true ),
+            (Operation::Intersection, false, true,  false, true ),
+            (Operation::Intersection, false, false, true,  false),
+            (Operation::Intersection, false, false, false, false),
+            (Operation::Difference,   true,  true,  true,  false),
+            (Operation::Difference,   true,  true,  false, true ),
+            (Operation::Difference,   true,  false, true,  false),
+            (Operation::Difference,   true,  false, false, true ),
+            (Operation::Difference,   false, true,  true,  true ),
+            (Operation::Difference,   false, true,  false, true ),
+            (Operation::Difference,   false, false, true,  false),
+            (Operation::Difference,   false, false, false, false),
+        ];
+
+        for (op, lhit, inl, inr, allowed) in cases {
+            assert_eq!(Csg::intersection_allowed(op, lhit, inl, inr), allowed, "{:?} {} {} {}", op, lhit, inl, inr);
+        }
+    }
+
+    #[test]
+    fn filtering() {
+
+        let cases = vec![
+            (Operation::Union,        0, 3),
+            (Operation::Intersection, 1, 2),
+            (Operation::Difference,   0, 1),
+        ];
+
+        for (op, x0, x1) in cases {
+
+            let s1 = ray::Sphere::unit();
+            let s2 = ray::Sphere::unit();
+            let (id1, id2) = (s1.id(), s2.id());
+
+            let c  = Csg::new(op, s1, s2);
+            let xs = vec![(ray::Isect::isect(1.0, id1), true),
+                          (ray::Isect::isect(2.0, id2), false),
+                          (ray::Isect::isect(3.0, id1), true),
+                          (ray::Isect::isect(4.0, id2), false)];
+
+            let result = c.filter_intersections(xs.clone());
+
+            assert_eq!(result, vec![xs[x0].0, xs[x1].0]);
+        }
+    }
+
+    #[test]
+    fn ray_misses() {
+
+        let c = Csg::new(Operation::Union, ray::Sphere::unit(), ray::Sphere::unit());
+        let r = ray::Ray::new(geo::Point3::new(0.0, 2.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+
+        assert!(c.intersect(&r).is_empty());
+    }
+
+    #[test]
+    fn ray_hits() {
+
+        let s1 = ray::Sphere::unit();
+        let mut s2 = ray::Sphere::unit();
+        s2.set_transform(matrix::Matrix::translate(0.0, 0.0, 0.5)).unwrap();
+        let (id1, id2) = (s1.id(), s2.id());
+
+        let c  = Csg::new(Operation::Union, s1, s2);
+        let r  = ray::Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let xs = c.intersect(&r);
+
+        assert_eq!(xs, vec![ray::Isect::isect(4.0, id1), ray::Isect::isect(6.5, id2)]);
+    }
+
+    #[test]
+    fn nested_csg_and_groups() {
+
+        // (s1 - s2) intersected with a group holding s3, where s2 carves the
+        // front off s1 and s3 is large enough to keep all of what remains
+        let s1 = ray::Sphere::unit();
+        let mut s2 = ray::Sphere::unit();
+        s2.set_transform(matrix::Matrix::translate(0.0, 0.0,-1.5)).unwrap();
+        let mut s3 = ray::Sphere::unit();
+        s3.set_transform(matrix::Matrix::scale(3.0, 3.0, 3.0)).unwrap();
+
+        let (id1, id2) = (s1.id(), s2.id());
+
+        let mut g = group::Group::new();
+        g.add_child(s3);
+
+        let diff = Csg::new(Operation::Difference, s1, s2);
+        let c    = Csg::new(Operation::Intersection, diff, g);
+
+        let r  = ray::Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let xs = c.intersect(&r);
+
+        assert_eq!(xs, vec![ray::Isect::isect(4.5, id2), ray::Isect::isect(6.0, id1)]);
+        assert!(shape::Shape::from(c).includes(id2));
+    }
+
+    #[test]
+    fn transformed_csg_normals() {
+
+        let s1 = ray::Sphere::unit();
+        let mut s2 = ray::Sphere::unit();
+        s2.set_transform(matrix::Matrix::translate(0.0, 0.0, 0.5)).unwrap();
+        let id1 = s1.id();
+
+        let mut c = Csg::new(Operation::Union, s1, s2);
+        c.set_transform(matrix::Matrix::translate(0.0, 0.0, 10.0)).unwrap();
+
+        let c  = shape::Shape::from(c);
+        let r  = ray::Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let xs = c.intersect(&r);
+
+        assert_eq!(xs[0], ray::Isect::isect(14.0, id1));
+
+        let n = c.find(id1).unwrap().normal_at(r.position(xs[0].t));
+        assert_eq!(n, Some(geo::Vec3::new(0.0, 0.0,-1.0)));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::geo;
+use crate::matrix;
+use crate::quaternion;
+
+// An affine transform split into the pieces it was built from, such that
+//
+//     m = translation * rotation * shear * scale
+//
+// where shear is Matrix::shear(xy, xz, 0, yz, 0, 0). Each piece can be
+// blended on its own, which a raw matrix cannot.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct Decomposition {
+    pub translation:geo::Vec3,
+    pub rotation:quaternion::Quaternion,
+    pub scale:geo::Vec3,
+    // xy, xz, yz
+    pub shear:[geo::Float;3],
+}
+
+impl Decomposition {
+
+    // None for projective or singular matrices, which have no such split
+    pub fn new(m:&matrix::Matrix) -> Option<Decomposition> {
+
+        if !m.is_invertible() ||
+           m.get(3,0).abs() > geo::EPSILON ||
+           m.get(3,1).abs() > geo::EPSILON ||
+           m.get(3,2).abs() > geo::EPSILON {
+            return None;
+        }
+
+        let w = m.get(3,3);
+        let column = |c:usize| geo::Vec3::new(m.get(0,c)/w, m.get(1,c)/w, m.get(2,c)/w);
+
+        let translation = column(3);
+        let (c0, c1, c2) = (column(0), column(1), column(2));
+
+        // Gram-Schmidt on the columns, what is removed along the way is shear
+        let mut sx = c0.len();
+        let x = c0/sx;
+
+        let xy = x.dot(c1);
+        let c1 = c1 - x*xy;
+        let mut sy = c1.len();
+        let y = c1/sy;
+
+        let xz = x.dot(c2);
+        let yz = y.dot(c2);
+        let c2 = c2 - x*xz - y*yz;
+        let mut sz = c2.len();
+        let z = c2/sz;
+
+        // as fractions of the scale they were removed from, which a mirror
+        // below leaves unchanged since it flips both
+        let shear = [xy/sy, xz/sz, yz/sz];
+
+        // a mirrored basis is folded into the scale so the rotation stays proper
+        let (mut x, mut y, mut z) = (x, y, z);
+        if x.dot(y.cross(z)) < 0.0 {
+            sx = -sx;
+            sy = -sy;
+            sz = -sz;
+            x  = -x;
+            y  = -y;
+            z  = -z;
+        }
+
+        let rotation = matrix::Matrix::new([[x.x, y.x, z.x, 0.0],
+                                            [x.y, y.y, z.y, 0.0],
+                                            [x.z, y.z, z.z, 0.0],
+                                            [0.0, 0.0, 0.0, 1.0]]);
+
+        Some(Decomposition {translation,
+                            rotation:quaternion::Quaternion::from_matrix(&rotation),
+                            scale:geo::Vec3::new(sx, sy, sz),
+                            shear})
+    }
+
+    pub fn compose(&self) -> matrix::Matrix {
+
+        matrix::Matrix::translate(self.translation.x, self.translation.y, self.translation.z) *
+        self.rotation.to_matrix() *
+        matrix::Matrix::shear(self.shear[0], self.shear[1], 0.0, self.shear[2], 0.0, 0.0) *
+        matrix::Matrix::scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    // blend towards other, t = 0 gives self and t = 1 gives other
+    pub fn interpolate(&self, other:&Decomposition, t:geo::Float) -> Decomposition {
+
+        let lerp = |a:geo::Float, b:geo::Float| a + (b - a)*t;
+
+        Decomposition {translation:self.translation + (other.translation - self.translation)*t,
+                       rotation:self.rotation.slerp(other.rotation, t),
+                       scale:self.scale + (other.scale - self.scale)*t,
+                       shear:[lerp(self.shear[0], other.shear[0]),
+                              lerp(self.shear[1], other.shear[1]),
+                              lerp(self.shear[2], other.shear[2])]}
+    }
+}
+
+impl matrix::Matrix4 {
+
+    pub fn decompose(&self) -> Option<Decomposition> {
+        Decomposition::new(self)
+    }
+
+    // the transform a fraction t of the way from self to other, None if
+    // either end cannot be decomposed
+    pub fn interpolate(&self, other:&matrix::Matrix4, t:geo::Float) -> Option<matrix::Matrix4> {
+
+        let a = self.decompose()?;
+        let b = other.decompose()?;
+
+        Some(a.interpolate(&b, t).compose())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::consts::PI;
+
+    #[test]
+    fn simple_parts() {
+
+        let d = matrix::Matrix::translate(1.0, 2.0, 3.0).decompose().unwrap();
+        assert_eq!(d.translation, geo::Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(d.rotation, quaternion::Quaternion::identity());
+        assert_eq!(d.scale, geo::Vec3::new(1.0, 1.0, 1.0));
+
+        let d = matrix::Matrix::scale(2.0, 3.0, 4.0).decompose().unwrap();
+        assert_eq!(d.scale, geo::Vec3::new(2.0, 3.0, 4.0));
+        assert_eq!(d.shear, [0.0, 0.0, 0.0]);
+
+        let d = matrix::Matrix::rotate_z(PI/3.0).decompose().unwrap();
+        assert_eq!(d.rotation, quaternion::Quaternion::from_axis_angle(geo::Vec3::new(0.0, 0.0, 1.0), PI/3.0));
+    }
+
+    #[test]
+    fn round_trip() {
+
+        let transforms = vec![
+            matrix::Matrix::identity(),
+            matrix::Matrix::identity().then_scale(2.0, 0.5, 3.0).then_rotate_y(1.0).then_translate(4.0,-1.0, 2.0),
+            matrix::Matrix::identity().then_shear(0.5, 0.2, 0.0, 0.7, 0.0, 0.0).then_scale(1.0, 2.0, 3.0).then_rotate_x(-0.4),
+            matrix::Matrix::identity().then_shear(0.3, 0.0, 0.4, 0.0, 0.1, 0.2).then_rotate_z(2.0).then_translate(0.0, 5.0, 0.0),
+            matrix::Matrix::scale(-1.0, 1.0, 1.0).then_rotate(geo::Vec3::new(1.0, 1.0, 0.0), 0.9),
+            matrix::Matrix::identity().then_shear(0.5, 0.0, 0.0, 0.0, 0.0, 0.0).then_scale(-1.0, 1.0, 1.0),
+            matrix::Matrix::identity().then_shear(0.3, 0.6, 0.0,-0.2, 0.0, 0.0).then_scale(2.0,-3.0, 1.0).then_rotate_y(0.7),
+        ];
+
+        for m in transforms {
+            assert_eq!(m.decompose().unwrap().compose(), m);
+        }
+    }
+
+    #[test]
+    fn undecomposable() {
+
+        assert!(matrix::Matrix::scale(1.0, 0.0, 1.0).decompose().is_none());
+
+        let mut projective = matrix::Matrix::identity();
+        projective.set(3, 2, 0.5);
+        assert!(projective.decompose().is_none());
+        assert!(projective.interpolate(&matrix::Matrix::identity(), 0.5).is_none());
+    }
+
+    #[test]
+    fn interpolate_endpoints() {
+
+        let a = matrix::Matrix::identity().then_scale(1.0, 2.0, 1.0).then_rotate_x(0.3).then_translate(1.0, 0.0, 0.0);
+        let b = matrix::Matrix::identity().then_scale(3.0, 1.0, 2.0).then_rotate_y(2.0).then_translate(0.0, 4.0,-2.0);
+
+        assert_eq!(a.interpolate(&b, 0.0).unwrap(), a);
+        assert_eq!(a.interpolate(&b, 1.0).unwrap(), b);
+    }
+
+    #[test]
+    fn interpolate_midpoint() {
+
+        let a = matrix::Matrix::identity().then_scale(1.0, 1.0, 1.0).then_translate(0.0, 0.0, 0.0);
+        let b = matrix::Matrix::identity().then_scale(3.0, 3.0, 3.0).then_rotate_z(PI/2.0).then_translate(4.0, 2.0, 0.0);
+
+        let mid = a.interpolate(&b, 0.5).unwrap();
+        let e   = matrix::Matrix::identity().then_scale(2.0, 2.0, 2.0).then_rotate_z(PI/4.0).then_translate(2.0, 1.0, 0.0);
+
+        assert_eq!(mid, e);
+    }
+}
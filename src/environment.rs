@@ -0,0 +1,679 @@
+use crate::geo;
+use crate::rng;
+use crate::color;
+use geo::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// What lies beyond the scene, seen by rays that miss everything and lighting
+// the scene from every direction. Directions are unit vectors with +y up.
+#[derive(Debug,Clone)]
+pub enum Environment {
+    Solid(color::Color),
+    // blended by height from straight down to straight up
+    Gradient { bottom:color::Color, top:color::Color },
+    Map(EnvMap),
+}
+
+impl Environment {
+
+    pub fn black() -> Environment {
+        Environment::Solid(color::Color::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn radiance(&self, dir:geo::Vec3) -> color::Color {
+        match self {
+            Environment::Solid(c) => *c,
+            Environment::Gradient { bottom, top } => {
+                let t = 0.5*(dir.y + 1.0);
+                *bottom*(1.0 - t) + *top*t
+            }
+            Environment::Map(m) => m.radiance(dir),
+        }
+    }
+
+    // True if it gives off no light at all, so integrators need not sample it.
+    // Only a black solid colour is known to be dark.
+    pub fn is_black(&self) -> bool {
+        match self {
+            Environment::Solid(c) => c.red <= 0.0 && c.green <= 0.0 && c.blue <= 0.0,
+            _ => false,
+        }
+    }
+
+    // A direction toward the environment drawn in proportion to how bright it
+    // is there, for maps, or uniformly over the sphere otherwise, with the
+    // radiance from it and the pdf over solid angle. None for a black one.
+    pub fn sample(&self, rng:&mut rng::Pcg32) -> Option<(geo::Vec3, color::Color, geo::Float)> {
+
+        if self.is_black() {
+            return None;
+        }
+
+        let dir = match self {
+            Environment::Map(m) => m.sample(rng),
+            _                   => uniform_sphere(rng),
+        };
+
+        let pdf = self.pdf(dir);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((dir, self.radiance(dir), pdf))
+    }
+
+    // the density sample draws dir with
+    pub fn pdf(&self, dir:geo::Vec3) -> geo::Float {
+
+        if self.is_black() {
+            return 0.0;
+        }
+
+        match self {
+            Environment::Map(m) => m.pdf(dir),
+            _                   => 1.0/(4.0*PI),
+        }
+    }
+
+    // A 64 bit FNV-1a hash of the kind of environment and every colour in it,
+    // the same from run to run, to tell whether two are the same without
+    // keeping either.
+    pub fn fingerprint(&self) -> u64 {
+
+        let mut h = Fnv(0xcbf2_9ce4_8422_2325);
+
+        match self {
+            Environment::Solid(c) => {
+                h.add(&[0]);
+                h.add_color(c);
+            }
+            Environment::Gradient { bottom, top } => {
+                h.add(&[1]);
+                h.add_color(bottom);
+                h.add_color(top);
+            }
+            Environment::Map(m) => {
+                h.add(&[2]);
+                h.add(&(m.width as u64).to_le_bytes());
+                h.add(&(m.height as u64).to_le_bytes());
+                for c in m.pixels.iter() {
+                    h.add_color(c);
+                }
+            }
+        }
+
+        h.0
+    }
+}
+
+struct Fnv(u64);
+
+impl Fnv {
+
+    fn add(&mut self, bytes:&[u8]) {
+        for b in bytes.iter() {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn add_color(&mut self, c:&color::Color) {
+        for v in [c.red, c.green, c.blue].iter() {
+            self.add(&v.to_le_bytes());
+        }
+    }
+}
+
+fn uniform_sphere(rng:&mut rng::Pcg32) -> geo::Vec3 {
+
+    let y   = 1.0 - 2.0*rng.next_float();
+    let r   = (1.0 - y*y).max(0.0).sqrt();
+    let phi = 2.0*PI*rng.next_float();
+
+    geo::Vec3::new(r*phi.cos(), y, r*phi.sin())
+}
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Io(io::Error),
+    // not an image this loader understands
+    Format(&'static str),
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvironmentError::Io(e)     => write!(f, "environment map i/o failed: {}", e),
+            EnvironmentError::Format(m) => write!(f, "bad environment map: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for EnvironmentError {}
+
+impl From<io::Error> for EnvironmentError {
+    fn from(e:io::Error) -> EnvironmentError {
+        EnvironmentError::Io(e)
+    }
+}
+
+// A latitude / longitude image of the surroundings. Row 0 looks straight up and
+// the last row straight down, columns go once round the horizon starting at -x
+// and passing through -z, +x and +z in turn.
+//
+// Pixels are drawn for sampling in proportion to their brightness times the
+// solid angle they cover, from a distribution over rows and one within each.
+#[derive(Debug,Clone)]
+pub struct EnvMap {
+    width:usize,
+    height:usize,
+    pixels:Vec<color::Color>,
+    // running totals of the row weights, normalised so the last is 1
+    row_cdf:Vec<geo::Float>,
+    // the same within each row, width per row
+    col_cdf:Vec<geo::Float>,
+    // chance of drawing each pixel
+    pixel_pdf:Vec<geo::Float>,
+}
+
+impl EnvMap {
+
+    // None unless there is one pixel for each of width*height
+    pub fn from_pixels(width:usize, height:usize, pixels:Vec<color::Color>) -> Option<EnvMap> {
+
+        if width == 0 || height == 0 || width.checked_mul(height) != Some(pixels.len()) {
+            return None;
+        }
+
+        let mut weights = Vec::with_capacity(width*height);
+
+        for y in 0..height {
+            let sin = (PI*(y as geo::Float + 0.5)/height as geo::Float).sin();
+            for x in 0..width {
+                weights.push(pixels[y*width + x].luminance().max(0.0)*sin);
+            }
+        }
+
+        // an entirely black map is sampled uniformly over the image instead
+        let total:geo::Float = weights.iter().sum();
+        if total <= 0.0 {
+            weights = vec![1.0; width*height];
+        }
+        let total:geo::Float = weights.iter().sum();
+
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut col_cdf = Vec::with_capacity(width*height);
+        let mut running = 0.0;
+
+        for row in weights.chunks(width) {
+
+            let row_total:geo::Float = row.iter().sum();
+            let mut in_row = 0.0;
+
+            for w in row.iter() {
+                in_row += w;
+                col_cdf.push(if row_total > 0.0 { in_row/row_total } else { 0.0 });
+            }
+
+            running += row_total;
+            row_cdf.push(running/total);
+        }
+
+        let pixel_pdf = weights.iter().map(|w| w/total).collect();
+
+        Some(EnvMap {width, height, pixels,
+                     row_cdf, col_cdf, pixel_pdf})
+    }
+
+    // reads a Radiance .hdr (RGBE) or a .pfm, going by the file extension
+    pub fn load<P:AsRef<Path>>(path:P) -> Result<EnvMap, EnvironmentError> {
+
+        let path  = path.as_ref();
+        let bytes = fs::read(path)?;
+
+        let (width, height, pixels) = match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("hdr") => read_hdr(&bytes)?,
+            Some(e) if e.eq_ignore_ascii_case("pfm") => read_pfm(&bytes)?,
+            _ => return Err(EnvironmentError::Format("expected a .hdr or .pfm file")),
+        };
+
+        EnvMap::from_pixels(width, height, pixels).ok_or(EnvironmentError::Format("empty image"))
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    // the pixel a direction falls in
+    fn pixel(&self, dir:geo::Vec3) -> (usize, usize) {
+
+        let u = (dir.z.atan2(dir.x) + PI)/(2.0*PI);
+        let v = dir.y.clamp(-1.0, 1.0).acos()/PI;
+
+        (((u*self.width as geo::Float) as usize).min(self.width - 1),
+         ((v*self.height as geo::Float) as usize).min(self.height - 1))
+    }
+
+    pub fn radiance(&self, dir:geo::Vec3) -> color::Color {
+        let (x, y) = self.pixel(dir);
+        self.pixels[y*self.width + x]
+    }
+
+    fn sample(&self, rng:&mut rng::Pcg32) -> geo::Vec3 {
+
+        let y   = find(&self.row_cdf, rng.next_float());
+        let row = &self.col_cdf[y*self.width..(y + 1)*self.width];
+        let x   = find(row, rng.next_float());
+
+        // anywhere within the pixel
+        let u = (x as geo::Float + rng.next_float())/self.width as geo::Float;
+        let v = (y as geo::Float + rng.next_float())/self.height as geo::Float;
+
+        let (phi, theta) = (u*2.0*PI - PI, v*PI);
+
+        geo::Vec3::new(theta.sin()*phi.cos(), theta.cos(), theta.sin()*phi.sin())
+    }
+
+    fn pdf(&self, dir:geo::Vec3) -> geo::Float {
+
+        let (x, y) = self.pixel(dir);
+        let sin    = (1.0 - dir.y*dir.y).max(0.0).sqrt();
+
+        if sin <= 0.0 {
+            return 0.0;
+        }
+
+        // a pixel covers 2pi/width by pi/height of longitude and latitude
+        self.pixel_pdf[y*self.width + x]*(self.width*self.height) as geo::Float/(2.0*PI*PI*sin)
+    }
+}
+
+// the first entry of a cumulative distribution above u
+fn find(cdf:&[geo::Float], u:geo::Float) -> usize {
+    cdf.partition_point(|&c| c <= u).min(cdf.len() - 1)
+}
+
+fn read_hdr(bytes:&[u8]) -> Result<(usize, usize, Vec<color::Color>), EnvironmentError> {
+
+    let mut pos = 0;
+    let mut line = || -> Result<&str, EnvironmentError> {
+        let end = bytes[pos..].iter().position(|&b| b == b'\n')
+            .ok_or(EnvironmentError::Format("truncated header"))?;
+        let l = std::str::from_utf8(&bytes[pos..pos + end]).map_err(|_| EnvironmentError::Format("header is not text"))?;
+        pos += end + 1;
+        Ok(l.trim_end_matches('\r'))
+    };
+
+    let magic = line()?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err(EnvironmentError::Format("not a radiance hdr file"));
+    }
+
+    // variables up to a blank line, only the pixel format matters
+    loop {
+        let l = line()?;
+        if l.is_empty() {
+            break;
+        }
+        if l.starts_with("FORMAT=") && l != "FORMAT=32-bit_rle_rgbe" {
+            return Err(EnvironmentError::Format("only rgbe pixels are supported"));
+        }
+    }
+
+    let res:Vec<&str> = line()?.split_whitespace().collect();
+    let (height, width) = match res.as_slice() {
+        ["-Y", h, "+X", w] => (h.parse::<usize>(), w.parse::<usize>()),
+        _ => return Err(EnvironmentError::Format("only -Y h +X w orientation is supported")),
+    };
+    let (height, width) = match (height, width) {
+        (Ok(h), Ok(w)) => (h, w),
+        _ => return Err(EnvironmentError::Format("bad resolution")),
+    };
+
+    let mut data = &bytes[pos..];
+
+    // Every scanline takes at least four bytes, and one too wide to be run
+    // length encoded takes four per pixel. Checking the sizes against the data
+    // first keeps a bad header from asking for a huge allocation.
+    let flat_bytes = if (8..0x8000).contains(&width) { Some(4) } else { width.checked_mul(4) };
+    if width == 0 || height == 0 {
+        return Err(EnvironmentError::Format("empty image"));
+    }
+    if !matches!(flat_bytes, Some(b) if b <= data.len()) || height > data.len()/4 {
+        return Err(EnvironmentError::Format("truncated pixel data"));
+    }
+
+    let mut pixels = Vec::new();
+    let mut rgbe   = vec![[0u8;4]; width];
+
+    for _ in 0..height {
+        data = read_scanline(data, &mut rgbe)?;
+        pixels.extend(rgbe.iter().map(|&p| from_rgbe(p)));
+    }
+
+    Ok((width, height, pixels))
+}
+
+// one row of rgbe pixels, run length encoded per channel or stored flat,
+// returning what is left of the data
+fn read_scanline<'a>(data:&'a [u8], row:&mut [[u8;4]]) -> Result<&'a [u8], EnvironmentError> {
+
+    let short = EnvironmentError::Format("truncated pixel data");
+    let width = row.len();
+
+    if data.len() < 4 {
+        return Err(short);
+    }
+
+    let encoded = (8..0x8000).contains(&width) && data[0] == 2 && data[1] == 2 && data[2] & 0x80 == 0;
+
+    if !encoded {
+        if data.len() < 4*width {
+            return Err(short);
+        }
+        for (p, b) in row.iter_mut().zip(data.chunks(4)) {
+            p.copy_from_slice(b);
+        }
+        return Ok(&data[4*width..]);
+    }
+
+    if ((data[2] as usize) << 8 | data[3] as usize) != width {
+        return Err(EnvironmentError::Format("scanline width mismatch"));
+    }
+
+    let mut pos = 4;
+
+    for c in 0..4 {
+        let mut x = 0;
+        while x < width {
+
+            let count = *data.get(pos).ok_or(EnvironmentError::Format("truncated pixel data"))? as usize;
+            pos += 1;
+
+            if count > 128 {
+                let n = count - 128;
+                let v = *data.get(pos).ok_or(EnvironmentError::Format("truncated pixel data"))?;
+                pos += 1;
+                if x + n > width {
+                    return Err(EnvironmentError::Format("run past end of scanline"));
+                }
+                for p in row[x..x + n].iter_mut() {
+                    p[c] = v;
+                }
+                x += n;
+            } else {
+                if count == 0 || x + count > width || pos + count > data.len() {
+                    return Err(EnvironmentError::Format("bad run in scanline"));
+                }
+                for (p, &v) in row[x..x + count].iter_mut().zip(data[pos..pos + count].iter()) {
+                    p[c] = v;
+                }
+                pos += count;
+                x += count;
+            }
+        }
+    }
+
+    Ok(&data[pos..])
+}
+
+fn from_rgbe(p:[u8;4]) -> color::Color {
+
+    if p[3] == 0 {
+        return color::Color::new(0.0, 0.0, 0.0);
+    }
+
+    let f = (2.0 as geo::Float).powi(p[3] as i32 - (128 + 8));
+
+    color::Color::new((p[0] as geo::Float + 0.5)*f, (p[1] as geo::Float + 0.5)*f, (p[2] as geo::Float + 0.5)*f)
+}
+
+fn read_pfm(bytes:&[u8]) -> Result<(usize, usize, Vec<color::Color>), EnvironmentError> {
+
+    // four whitespace separated header fields, the last followed by a single
+    // whitespace byte before the pixels
+    let mut fields = Vec::new();
+    let mut pos = 0;
+
+    while fields.len() < 4 {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(EnvironmentError::Format("truncated header"));
+        }
+        fields.push(std::str::from_utf8(&bytes[start..pos]).map_err(|_| EnvironmentError::Format("header is not text"))?);
+    }
+
+    let channels:usize = match fields[0] {
+        "PF" => 3,
+        "Pf" => 1,
+        _    => return Err(EnvironmentError::Format("not a pfm file")),
+    };
+
+    let width  = fields[1].parse::<usize>().map_err(|_| EnvironmentError::Format("bad width"))?;
+    let height = fields[2].parse::<usize>().map_err(|_| EnvironmentError::Format("bad height"))?;
+    // its sign gives the byte order, negative for little endian
+    let scale  = fields[3].parse::<f32>().map_err(|_| EnvironmentError::Format("bad scale"))?;
+
+    let data = &bytes[(pos + 1).min(bytes.len())..];
+
+    // sizes come from the file, so an absurd header must not overflow
+    let size = (4*channels).checked_mul(width).and_then(|n| n.checked_mul(height))
+        .ok_or(EnvironmentError::Format("image too large"))?;
+
+    if data.len() < size {
+        return Err(EnvironmentError::Format("truncated pixel data"));
+    }
+
+    let value = |i:usize| {
+        let b = [data[4*i], data[4*i + 1], data[4*i + 2], data[4*i + 3]];
+        (if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }) as geo::Float
+    };
+
+    // rows are stored bottom up
+    let mut pixels = Vec::with_capacity(width*height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let i = (y*width + x)*channels;
+            pixels.push(if channels == 3 {
+                color::Color::new(value(i), value(i + 1), value(i + 2))
+            } else {
+                color::Color::new(value(i), value(i), value(i))
+            });
+        }
+    }
+
+    Ok((width, height, pixels))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // loads bytes as though they were the file name, which is removed again
+    fn load_bytes(name:&str, bytes:&[u8]) -> Result<EnvMap, EnvironmentError> {
+
+        let path = crate::temp_path(name);
+        fs::write(&path, bytes).unwrap();
+
+        let map = EnvMap::load(&path);
+        fs::remove_file(&path).unwrap();
+        map
+    }
+
+    fn grey(v:geo::Float) -> color::Color {
+        color::Color::new(v, v, v)
+    }
+
+    #[test]
+    fn solid_and_gradient() {
+
+        let up = geo::Vec3::new(0.0, 1.0, 0.0);
+
+        assert_eq!(Environment::black().radiance(up), grey(0.0));
+        assert!(Environment::black().is_black());
+        assert!(Environment::black().sample(&mut rng::Pcg32::new(0, 0)).is_none());
+
+        let sky = Environment::Gradient {bottom:grey(0.0), top:color::Color::new(0.5, 0.7, 1.0)};
+        assert_eq!(sky.radiance(up), color::Color::new(0.5, 0.7, 1.0));
+        assert_eq!(sky.radiance(geo::Vec3::new(1.0, 0.0, 0.0)), color::Color::new(0.25, 0.35, 0.5));
+        assert_eq!(sky.pdf(up), 1.0/(4.0*PI));
+    }
+
+    #[test]
+    fn map_directions() {
+
+        // top row 0..3 and bottom row 4..7 round the horizon from -x
+        let map = EnvMap::from_pixels(4, 2, (0..8).map(|v| grey(v as geo::Float)).collect()).unwrap();
+        let d   = |x:geo::Float, y:geo::Float, z:geo::Float| map.radiance(geo::Vec3::new(x, y, z).norm());
+
+        assert_eq!(d(-1.0, 0.1, -0.1), grey(0.0));
+        assert_eq!(d(0.1, 0.1, -1.0), grey(1.0));
+        assert_eq!(d(1.0, 0.1, 0.1), grey(2.0));
+        assert_eq!(d(-0.1, 0.1, 1.0), grey(3.0));
+        assert_eq!(d(1.0, -0.1, 0.1), grey(6.0));
+
+        assert!(EnvMap::from_pixels(4, 2, vec![grey(1.0); 7]).is_none());
+    }
+
+    #[test]
+    fn map_importance_sampling() {
+
+        // dark all round but for one bright pixel just above the horizon
+        let (w, h) = (16, 8);
+        let mut pixels = vec![grey(0.1); w*h];
+        pixels[3*w + 5] = grey(50.0);
+
+        let env = Environment::Map(EnvMap::from_pixels(w, h, pixels.clone()).unwrap());
+
+        // the light arriving from all round, added up pixel by pixel
+        let exact:geo::Float = (0..h).map(|y| {
+            let band = ((y as geo::Float)*PI/h as geo::Float).cos() - ((y + 1) as geo::Float*PI/h as geo::Float).cos();
+            (0..w).map(|x| pixels[y*w + x].red*band*2.0*PI/w as geo::Float).sum::<geo::Float>()
+        }).sum();
+
+        let mut rng = rng::Pcg32::new(4, 0);
+        let n = 50000;
+        let mut estimate = 0.0;
+        let mut pdf_mass = 0.0;
+
+        for _ in 0..n {
+            let (dir, l, pdf) = env.sample(&mut rng).unwrap();
+            assert!((pdf - env.pdf(dir)).abs() < 1e-6*pdf.max(1.0));
+            estimate += l.red/pdf/n as geo::Float;
+            pdf_mass += env.pdf(uniform_sphere(&mut rng))*4.0*PI/n as geo::Float;
+        }
+
+        assert!((estimate - exact).abs() < 0.01*exact, "{} vs {}", estimate, exact);
+        assert!((pdf_mass - 1.0).abs() < 0.05, "{}", pdf_mass);
+    }
+
+    #[test]
+    fn rgbe_pixels() {
+
+        assert_eq!(from_rgbe([0, 0, 0, 0]), grey(0.0));
+        assert_eq!(from_rgbe([127, 255, 63, 129]), color::Color::new(127.5/128.0, 255.5/128.0, 63.5/128.0));
+    }
+
+    #[test]
+    fn load_hdr() {
+
+        let header = b"#?RADIANCE\n# made by a test\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+        let row0:Vec<[u8;4]> = (0..8).map(|x| [x*10, 7, 200, 130]).collect();
+        let row1:Vec<[u8;4]> = (0..8).map(|x| [5, x, 9, 128 + x]).collect();
+
+        // the first row stored flat
+        let mut flat = header.clone();
+        for p in row0.iter() {
+            flat.extend_from_slice(p);
+        }
+        // and the second run length encoded, channel by channel
+        flat.extend_from_slice(&[2, 2, 0, 8]);
+        flat.extend_from_slice(&[128 + 8, 5]);
+        flat.extend_from_slice(&[8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        flat.extend_from_slice(&[128 + 8, 9]);
+        flat.extend_from_slice(&[4, 128, 129, 130, 131, 128 + 4, 0]);
+
+        let map = load_bytes("test_env.hdr", &flat).unwrap();
+        assert_eq!((map.get_width(), map.get_height()), (8, 2));
+
+        for (p, &rgbe) in map.pixels[..8].iter().zip(row0.iter()) {
+            assert_eq!(*p, from_rgbe(rgbe));
+        }
+        for (p, &rgbe) in map.pixels[8..12].iter().zip(row1.iter()) {
+            assert_eq!(*p, from_rgbe(rgbe));
+        }
+        for (x, p) in map.pixels[12..].iter().enumerate() {
+            assert_eq!(*p, from_rgbe([5, 4 + x as u8, 9, 0]));
+        }
+    }
+
+    #[test]
+    fn fingerprint() {
+
+        let red  = Environment::Solid(color::Color::new(1.0, 0.0, 0.0));
+        let grad = Environment::Gradient {bottom:color::Color::new(1.0, 0.0, 0.0), top:color::Color::new(1.0, 0.0, 0.0)};
+
+        assert_eq!(red.fingerprint(), Environment::Solid(color::Color::new(1.0, 0.0, 0.0)).fingerprint());
+        assert_ne!(red.fingerprint(), Environment::black().fingerprint());
+        assert_ne!(red.fingerprint(), grad.fingerprint());
+
+        let map = |v:f32| {
+            let mut bytes = b"Pf\n1 1\n-1.0\n".to_vec();
+            bytes.extend_from_slice(&v.to_le_bytes());
+            Environment::Map(load_bytes("fingerprint.pfm", &bytes).unwrap()).fingerprint()
+        };
+
+        assert_eq!(map(2.0), map(2.0));
+        assert_ne!(map(2.0), map(3.0));
+    }
+
+    #[test]
+    fn load_pfm() {
+
+        // little endian colour, rows bottom up
+        let mut rgb = b"PF\n2 2\n-1.0\n".to_vec();
+        for v in [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0, 0.0, 0.5, 0.25, 8.0, 9.0, 10.0].iter() {
+            rgb.extend_from_slice(&v.to_le_bytes());
+        }
+        let map = load_bytes("test_env.pfm", &rgb).unwrap();
+        assert_eq!(map.pixels[0], color::Color::new(0.0, 0.5, 0.25));
+        assert_eq!(map.pixels[3], color::Color::new(4.0, 5.0, 6.0));
+
+        // big endian greyscale
+        let mut mono = b"Pf 1 2 1.0\n".to_vec();
+        for v in [3.0f32, 7.0].iter() {
+            mono.extend_from_slice(&v.to_be_bytes());
+        }
+        let map = load_bytes("test_env_mono.pfm", &mono).unwrap();
+        assert_eq!(map.pixels, vec![grey(7.0), grey(3.0)]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+
+        assert!(matches!(load_bytes("not_env.hdr", b"P3\n1 1\n255\n0 0 0\n"), Err(EnvironmentError::Format(_))));
+        assert!(matches!(EnvMap::load("ppm/sphere.ppm"), Err(EnvironmentError::Format(_))));
+        assert!(matches!(EnvMap::load(crate::temp_path("missing.pfm")), Err(EnvironmentError::Io(_))));
+
+        // headers claiming far more pixels than the file holds
+        let huge = |name:&str, bytes:&[u8]| matches!(load_bytes(name, bytes), Err(EnvironmentError::Format(_)));
+
+        assert!(huge("huge_env.pfm", b"PF\n4294967296 4294967296\n-1.0\n\0\0\0\0"));
+        assert!(huge("huge_env.pfm", b"PF\n100000 100000\n-1.0\n\0\0\0\0"));
+        assert!(huge("huge_env.hdr", b"#?RADIANCE\n\n-Y 2 +X 1000000000000\n\0\0\0\0"));
+        assert!(huge("huge_env.hdr", b"#?RADIANCE\n\n-Y 1000000000000 +X 16\n\0\0\0\0"));
+    }
+}
@@ -1,156 +1,202 @@
 use std::ops;
 
-const VECTOR_W:f64 = 0.0;
-const  POINT_W:f64 = 1.0;
-pub const  EPSILON:f64 = 0.00001;
+// all the math runs at this precision, f64 unless the f32 feature is enabled
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+// PI and friends at the same precision
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+
+// tolerance for comparisons, f32 carries about half the digits of f64
+#[cfg(not(feature = "f32"))]
+pub const EPSILON:Float = 0.00001;
+#[cfg(feature = "f32")]
+pub const EPSILON:Float = 0.001;
+
+// Points and vectors are kept as separate types so only the operations that
+// make sense between them compile: point - point is a vector, point + vector
+// is a point, and points cannot be added, scaled or normalised at all.
 
+#[derive(Debug,Copy,Clone)]
+pub struct Point3 {
+    pub x:Float,
+    pub y:Float,
+    pub z:Float,
+}
 
 #[derive(Debug,Copy,Clone)]
-pub struct Geo {
-    pub x:f64,
-    pub y:f64,
-    pub z:f64,
-    pub w:f64
+pub struct Vec3 {
+    pub x:Float,
+    pub y:Float,
+    pub z:Float,
 }
 
 
-impl Geo {
+impl Point3 {
 
-    pub fn new(x:f64, y:f64, z:f64, w:f64) -> Geo {
-        Geo {x:x, y:y, z:z, w:w}
+    pub fn new(x:Float, y:Float, z:Float) -> Point3 {
+        Point3 {x, y, z}
     }
 
-    pub fn with_vec(tup: Vec<f64>) -> Geo {
-
-        Geo {x:tup[0], y:tup[1], z:tup[2], w:tup[3]}
+    pub fn origin() -> Point3 {
+        Point3 {x:0.0, y:0.0, z:0.0}
     }
+}
 
+impl Vec3 {
 
-    pub fn point (x:f64, y:f64, z:f64) -> Geo {
-        Geo {x:x, y:y, z:z, w:POINT_W}
-    }                
-                 
-    pub fn vector (x:f64, y:f64, z:f64) -> Geo {
-        Geo {x:x, y:y, z:z, w:VECTOR_W}
-    }
-
-    pub fn is_point (&self) -> bool {
-        self.w == POINT_W
+    pub fn new(x:Float, y:Float, z:Float) -> Vec3 {
+        Vec3 {x, y, z}
     }
 
-    pub fn is_vector (&self) -> bool {
-        self.w == VECTOR_W
+    pub fn zero() -> Vec3 {
+        Vec3 {x:0.0, y:0.0, z:0.0}
     }
 
-    pub fn len(&self) -> f64 {
+    pub fn len(&self) -> Float {
         let s = self.x*self.x +
                 self.y*self.y +
-                self.z*self.z +
-                self.w*self.w;
+                self.z*self.z;
         s.sqrt()
     }
 
-    pub fn norm(&self) -> Geo {
+    pub fn norm(&self) -> Vec3 {
         let mag = self.len();
-        return *self/mag;
+        *self/mag
     }
-    
-    pub fn dot(&self, other:Self) -> f64 {
+
+    pub fn dot(&self, other:Self) -> Float {
         self.x*other.x +
         self.y*other.y +
-        self.z*other.z +
-        self.w*other.w 
+        self.z*other.z
     }
 
-    pub fn cross(&self, other:Self) -> Geo {
-       Geo::vector( self.y*other.z - self.z*other.y,
-                    self.z*other.x - self.x*other.z,
-                    self.x*other.y - self.y*other.x)
+    pub fn cross(&self, other:Self) -> Vec3 {
+       Vec3::new(self.y*other.z - self.z*other.y,
+                 self.z*other.x - self.x*other.z,
+                 self.x*other.y - self.y*other.x)
     }
 
-    pub fn reflect(&self, normal:Self) -> Geo {
+    pub fn reflect(&self, normal:Self) -> Vec3 {
 
-        return *self - (normal*2.0)*self.dot(normal);
+        *self - (normal*2.0)*self.dot(normal)
 
     }
 }
 
-impl ops::Add for Geo {
-    type Output = Geo;
+impl ops::Sub for Point3 {
+    type Output = Vec3;
 
-    fn add(self,rhs:Geo) -> Geo {
-       Geo::new(self.x + rhs.x,
-                self.y + rhs.y,
-                self.z + rhs.z,
-                self.w + rhs.w)
+    fn sub(self,rhs:Point3) -> Vec3 {
+        Vec3::new(self.x - rhs.x,
+                  self.y - rhs.y,
+                  self.z - rhs.z)
     }
 }
 
+impl ops::Add<Vec3> for Point3 {
+    type Output = Point3;
+
+    fn add(self,rhs:Vec3) -> Point3 {
+        Point3::new(self.x + rhs.x,
+                    self.y + rhs.y,
+                    self.z + rhs.z)
+    }
+}
 
-impl ops::Sub for Geo {
-    type Output = Geo;
+impl ops::Sub<Vec3> for Point3 {
+    type Output = Point3;
 
-    fn sub(self,rhs:Geo) -> Geo {
-        Geo::new(self.x - rhs.x,
-                 self.y - rhs.y,
-                 self.z - rhs.z,
-                 self.w - rhs.w)
+    fn sub(self,rhs:Vec3) -> Point3 {
+        Point3::new(self.x - rhs.x,
+                    self.y - rhs.y,
+                    self.z - rhs.z)
     }
 }
 
-impl ops::Neg for Geo {
-    type Output = Geo;
+impl ops::Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self,rhs:Vec3) -> Vec3 {
+       Vec3::new(self.x + rhs.x,
+                 self.y + rhs.y,
+                 self.z + rhs.z)
+    }
+}
 
-    fn neg(self) -> Geo {
-        Geo::new(-self.x, -self.y, -self.z, -self.w)
-    }                
-}                    
-                     
-impl ops::Mul<f64> for Geo {
-    type Output = Geo;
 
-    fn mul(self,rhs:f64) -> Geo {
-       Geo::new(self.x * rhs,
-                self.y * rhs,
-                self.z * rhs,
-                self.w * rhs)
+impl ops::Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self,rhs:Vec3) -> Vec3 {
+        Vec3::new(self.x - rhs.x,
+                  self.y - rhs.y,
+                  self.z - rhs.z)
     }
 }
 
-impl ops::Mul<Geo> for f64 {
-    type Output = Geo;
+impl ops::Neg for Vec3 {
+    type Output = Vec3;
 
-    fn mul(self,rhs:Geo) -> Geo {
-       Geo::new(self * rhs.x,
-                self * rhs.y,
-                self * rhs.z,
-                self * rhs.w)
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
     }
 }
 
-impl ops::Div<f64> for Geo {
-    type Output = Geo;
+impl ops::Mul<Float> for Vec3 {
+    type Output = Vec3;
 
-    fn div(self,rhs:f64) -> Geo {
-        Geo::new(self.x / rhs,
-                 self.y / rhs,
-                 self.z / rhs,
-                 self.w / rhs)
+    fn mul(self,rhs:Float) -> Vec3 {
+       Vec3::new(self.x * rhs,
+                 self.y * rhs,
+                 self.z * rhs)
     }
 }
 
-impl PartialEq for Geo {
+impl ops::Mul<Vec3> for Float {
+    type Output = Vec3;
+
+    fn mul(self,rhs:Vec3) -> Vec3 {
+       Vec3::new(self * rhs.x,
+                 self * rhs.y,
+                 self * rhs.z)
+    }
+}
+
+impl ops::Div<Float> for Vec3 {
+    type Output = Vec3;
+
+    fn div(self,rhs:Float) -> Vec3 {
+        Vec3::new(self.x / rhs,
+                  self.y / rhs,
+                  self.z / rhs)
+    }
+}
+
+impl PartialEq for Point3 {
     fn eq(&self, other: &Self) -> bool {
         (self.x - other.x).abs() < EPSILON &&
         (self.y - other.y).abs() < EPSILON &&
-        (self.z - other.z).abs() < EPSILON &&
-        (self.w - other.w).abs() < EPSILON 
+        (self.z - other.z).abs() < EPSILON
     }
 }
 
+impl Eq for Point3 {}
 
+impl PartialEq for Vec3 {
+    fn eq(&self, other: &Self) -> bool {
+        (self.x - other.x).abs() < EPSILON &&
+        (self.y - other.y).abs() < EPSILON &&
+        (self.z - other.z).abs() < EPSILON
+    }
+}
 
-impl Eq for Geo {}
+impl Eq for Vec3 {}
 
 
 #[cfg(test)]
@@ -158,173 +204,153 @@ mod tests {
     use super::*;
 
     #[test]
-    fn tup_is_point () {
+    fn point_fields () {
 
-        let p = Geo::new(4.3,-4.2,3.1,1.0);
+        let p = Point3::new(4.3,-4.2,3.1);
 
-        assert_eq!(p.is_point(),  true);
-        assert_eq!(p.is_vector(), false);
+        assert_eq!(p.x, 4.3);
+        assert_eq!(p.y,-4.2);
+        assert_eq!(p.z, 3.1);
     }
 
     #[test]
-    fn tup_is_vector() {
-        let v = Geo::new(4.3,-4.2,3.1,0.0);
-
-        assert_eq!(v.is_point(),  false);
-        assert_eq!(v.is_vector(), true);
-    }
-    
-    #[test]
-    fn point_w_1() {
-        let t = Geo::point(1.0,-5.0,10.0);
-        assert_eq!(t.w,POINT_W);
-    }
+    fn vector_fields() {
+        let v = Vec3::new(4.3,-4.2,3.1);
 
-    #[test]
-    fn vector_w_0() {
-        let t = Geo::vector(1.0,-5.0,10.0);
-        assert_eq!(t.w,VECTOR_W);
+        assert_eq!(v.x, 4.3);
+        assert_eq!(v.y,-4.2);
+        assert_eq!(v.z, 3.1);
     }
 
     #[test]
     fn equality() {
-        let v  = Geo::vector(1.0, -1.0, 2.0);
-        let p  = Geo::point( 1.0, -1.0, 2.0);
-        let tp = Geo::new(1.0,-1.0,2.0,1.0);
-        let tv = Geo::new(1.0,-1.0,2.0,0.0);
-        
-        assert_eq!(v,tv);
-        
-        assert_ne!(v,p);
-        assert_ne!(v,tp);
-        
-        assert_eq!(p,tp);
-        
-        assert_ne!(p,v);
-        assert_ne!(p,tv);
+        let v  = Vec3::new(1.0, -1.0, 2.0);
+        let p  = Point3::new( 1.0, -1.0, 2.0);
+
+        assert_eq!(v,Vec3::new(1.0,-1.0,2.0));
+        assert_ne!(v,Vec3::new(1.0,-1.0,2.1));
+
+        assert_eq!(p,Point3::new(1.0,-1.0,2.0));
+        assert_ne!(p,Point3::new(1.1,-1.0,2.0));
     }
 
     #[test]
     fn addition() {
-        let a1 = Geo::new(3.0,-2.0,5.0,1.0);
-        let a2 = Geo::new(-2.0,3.0,1.0,0.0);
+        let a1 = Point3::new(3.0,-2.0,5.0);
+        let a2 = Vec3::new(-2.0,3.0,1.0);
 
-        let result = a1 + a2;
+        let result:Point3 = a1 + a2;
 
-        assert_eq!(result,Geo::new(1.0,1.0,6.0,1.0));
+        assert_eq!(result,Point3::new(1.0,1.0,6.0));
     }
 
     #[test]
     fn subtraction1() {
-        let a1 = Geo::point(3.0,2.0,1.0);
-        let a2 = Geo::point(5.0,6.0,7.0);
+        let a1 = Point3::new(3.0,2.0,1.0);
+        let a2 = Point3::new(5.0,6.0,7.0);
 
-        let result = a1 - a2;
+        let result:Vec3 = a1 - a2;
 
-        assert_eq!(result,Geo::new(-2.0,-4.0,-6.0,0.0));
-        assert!(result.is_vector());
+        assert_eq!(result,Vec3::new(-2.0,-4.0,-6.0));
     }
 
     #[test]
     fn subtraction2() {
-        let a1 = Geo::point(3.0,2.0,1.0);
-        let a2 = Geo::vector(5.0,6.0,7.0);
+        let a1 = Point3::new(3.0,2.0,1.0);
+        let a2 = Vec3::new(5.0,6.0,7.0);
 
-        let result = a1 - a2;
+        let result:Point3 = a1 - a2;
 
-        assert_eq!(result,Geo::new(-2.0,-4.0,-6.0,1.0));
-        assert!(result.is_point());
+        assert_eq!(result,Point3::new(-2.0,-4.0,-6.0));
     }
 
     #[test]
     fn subtraction3() {
-        let a1 = Geo::vector(3.0,2.0,1.0);
-        let a2 = Geo::vector(5.0,6.0,7.0);
+        let a1 = Vec3::new(3.0,2.0,1.0);
+        let a2 = Vec3::new(5.0,6.0,7.0);
 
-        let result = a1 - a2;
+        let result:Vec3 = a1 - a2;
 
-        assert_eq!(result,Geo::new(-2.0,-4.0,-6.0,0.0));
-        assert!(result.is_vector());
+        assert_eq!(result,Vec3::new(-2.0,-4.0,-6.0));
     }
 
     #[test]
     fn negate1() {
-        let z  = Geo::vector(0.0,0.0,0.0);
-        let a2 = Geo::vector(1.0,-2.0,-3.0);
+        let z  = Vec3::zero();
+        let a2 = Vec3::new(1.0,-2.0,-3.0);
 
         let result = z - a2;
 
-        assert_eq!(result,Geo::new(-1.0,2.0,3.0,0.0));
-        assert!(result.is_vector());
+        assert_eq!(result,Vec3::new(-1.0,2.0,3.0));
     }
 
     #[test]
     fn negate2() {
-        let a = Geo::new(1.0,-2.0,-3.0,-4.0);
+        let a = Vec3::new(1.0,-2.0,-3.0);
 
         let result = -a;
 
-        assert_eq!(result,Geo::new(-1.0,2.0,3.0,4.0));
+        assert_eq!(result,Vec3::new(-1.0,2.0,3.0));
     }
 
     #[test]
     fn cmul1() {
-        let a = Geo::new(1.0,-2.0,3.0,-4.0);
+        let a = Vec3::new(1.0,-2.0,3.0);
         let result = 3.5*a;
 
-        assert_eq!(result,Geo::new(3.5,-7.0,10.5,-14.0));
+        assert_eq!(result,Vec3::new(3.5,-7.0,10.5));
     }
 
     #[test]
     fn cmul2() {
-        let a = Geo::new(1.0,-2.0,3.0,-4.0);
+        let a = Vec3::new(1.0,-2.0,3.0);
         let result = 0.5 * a;
 
-        assert_eq!(result,Geo::new(0.5,-1.0,1.5,-2.0));
+        assert_eq!(result,Vec3::new(0.5,-1.0,1.5));
     }
 
     #[test]
     fn cdiv() {
-        let a = Geo::new(1.0,-2.0,3.0,-4.0);
+        let a = Vec3::new(1.0,-2.0,3.0);
         let result = a/2.0;
-        assert_eq!(result,Geo::new(0.5,-1.0,1.5,-2.0));
+        assert_eq!(result,Vec3::new(0.5,-1.0,1.5));
     }
 
     #[test]
     fn magnitude() {
-        let v1 = Geo::vector(1.0,0.0,0.0);
+        let v1 = Vec3::new(1.0,0.0,0.0);
         assert_eq!(v1.len(),1.0);
 
-        let v2 = Geo::vector(0.0,1.0,0.0);
+        let v2 = Vec3::new(0.0,1.0,0.0);
         assert_eq!(v2.len(),1.0);
 
-        let v3 = Geo::vector(0.0,0.0,1.0);
+        let v3 = Vec3::new(0.0,0.0,1.0);
         assert_eq!(v3.len(),1.0);
-        
-        let v4 = Geo::vector(1.0,2.0,3.0);
-        assert_eq!(v4.len(),14.0f64.sqrt());
-        
-        let v5 = Geo::vector(-1.0,-2.0,-3.0);
-        assert_eq!(v5.len(),14.0f64.sqrt());
+
+        let v4 = Vec3::new(1.0,2.0,3.0);
+        assert_eq!(v4.len(),Float::sqrt(14.0));
+
+        let v5 = Vec3::new(-1.0,-2.0,-3.0);
+        assert_eq!(v5.len(),Float::sqrt(14.0));
     }
 
     #[test]
     fn normalization() {
-        let v1 = Geo::vector(4.0,0.0,0.0);
-        assert_eq!(v1.norm(),Geo::vector(1.0,0.0,0.0));
+        let v1 = Vec3::new(4.0,0.0,0.0);
+        assert_eq!(v1.norm(),Vec3::new(1.0,0.0,0.0));
 
-        let v2 = Geo::vector(1.0,2.0,3.0);
-        let mag = 14.0f64.sqrt();
+        let v2 = Vec3::new(1.0,2.0,3.0);
+        let mag = Float::sqrt(14.0);
 
-        assert_eq!(v2.norm(),Geo::vector(1.0/mag, 2.0/mag, 3.0/mag));
-        assert_eq!(v2.norm().len(),1.0)
+        assert_eq!(v2.norm(),Vec3::new(1.0/mag, 2.0/mag, 3.0/mag));
+        assert!((v2.norm().len() - 1.0).abs() < EPSILON);
 
     }
 
     #[test]
     fn dot() {
-        let v1 = Geo::vector(1.0,2.0,3.0);
-        let v2 = Geo::vector(2.0,3.0,4.0);
+        let v1 = Vec3::new(1.0,2.0,3.0);
+        let v2 = Vec3::new(2.0,3.0,4.0);
 
         let result1 = v1.dot(v2);
         let result2 = v2.dot(v1);
@@ -335,33 +361,32 @@ mod tests {
 
     #[test]
     fn cross() {
-        let v1 = Geo::vector(1.0,2.0,3.0);
-        let v2 = Geo::vector(2.0,3.0,4.0);
+        let v1 = Vec3::new(1.0,2.0,3.0);
+        let v2 = Vec3::new(2.0,3.0,4.0);
 
         let result1 = v1.cross(v2);
         let result2 = v2.cross(v1);
 
         assert_eq!(result1,-result2);
-        assert_eq!(result1,Geo::vector(-1.0,2.0,-1.0));
-        assert_eq!(result2,Geo::vector(1.0,-2.0,1.0));
+        assert_eq!(result1,Vec3::new(-1.0,2.0,-1.0));
+        assert_eq!(result2,Vec3::new(1.0,-2.0,1.0));
     }
 }
 
 #[test]
 fn reflect_45() {
-    let v = Geo::vector(1.0, -1.0, 0.0);
-    let n = Geo::vector(0.0,1.0,0.0);
+    let v = Vec3::new(1.0, -1.0, 0.0);
+    let n = Vec3::new(0.0,1.0,0.0);
 
     let r = v.reflect(n);
-    assert_eq!(r,Geo::vector(1.0, 1.0, 0.0));
+    assert_eq!(r,Vec3::new(1.0, 1.0, 0.0));
 }
 
 #[test]
 fn reflect_slant() {
-    let v = Geo::vector(0.0, -1.0, 0.0);
-    let n = Geo::vector(1.0/(2.0f64).sqrt(),1.0/(2.0f64).sqrt(),0.0);
+    let v = Vec3::new(0.0, -1.0, 0.0);
+    let n = Vec3::new(1.0/Float::sqrt(2.0),1.0/Float::sqrt(2.0),0.0);
 
     let r = v.reflect(n);
-    assert_eq!(r,Geo::vector(1.0, 0.0, 0.0));
+    assert_eq!(r,Vec3::new(1.0, 0.0, 0.0));
 }
-
@@ -0,0 +1,295 @@
+use crate::ray;
+use crate::shape;
+use crate::matrix;
+use crate::bounds;
+
+// a collection of shapes transformed as one, children may themselves be groups
+#[derive(Debug,Clone)]
+pub struct Group {
+    id:i32,
+    transform:matrix::Transform,
+    // the enclosing groups' transforms composed into one
+    parent:matrix::Transform,
+    children:Vec<shape::Shape>,
+    // union of the children's bounds, in this group's object space
+    local_bounds:bounds::Aabb,
+}
+
+impl Default for Group {
+    fn default() -> Group {
+        Group::new()
+    }
+}
+
+impl Group {
+
+    pub fn new() -> Group {
+        Group {id:ray::next_shape_id(),
+               transform:matrix::Transform::identity(),
+               parent:matrix::Transform::identity(),
+               children:Vec::new(),
+               local_bounds:bounds::Aabb::empty()}
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn transform(&self) -> matrix::Matrix {
+        self.transform.matrix()
+    }
+
+    pub fn children(&self) -> &[shape::Shape] {
+        &self.children
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    pub fn add_child<S:Into<shape::Shape>>(&mut self, child:S) {
+
+        let mut child = child.into();
+        child.set_parent(self.child_parent());
+
+        self.local_bounds = self.local_bounds.merge(child.bounds());
+        self.children.push(child);
+    }
+
+    pub fn set_transform(&mut self, m:matrix::Matrix) -> Result<(), matrix::TransformError> {
+
+        self.transform = matrix::Transform::new(m)
+            .ok_or_else(|| matrix::TransformError::new("group", self.id, m))?;
+
+        self.update_children();
+        Ok(())
+    }
+
+    pub fn set_parent(&mut self, parent:matrix::Transform) {
+        self.parent = parent;
+        self.update_children();
+    }
+
+    // what every child sees as its parent, ours with our own transform inside
+    fn child_parent(&self) -> matrix::Transform {
+        self.parent.compose(&self.transform)
+    }
+
+    fn update_children(&mut self) {
+
+        let parent = self.child_parent();
+
+        for c in self.children.iter_mut() {
+            c.set_parent(parent);
+        }
+    }
+
+    // bounds in the parent's space
+    pub fn bounds(&self) -> bounds::Aabb {
+        self.local_bounds.transform(&self.transform.matrix())
+    }
+
+    pub fn intersect(&self, r:&ray::Ray) -> Vec<ray::Isect> {
+
+        let r = r.transform(self.transform.inverse());
+
+        if self.local_bounds.intersect(&r).is_none() {
+            return vec![];
+        }
+
+        let mut xs:Vec<ray::Isect> = self.children.iter()
+            .flat_map(|c| c.intersect(&r))
+            .collect();
+
+        xs.sort();
+        xs
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo;
+    use geo::consts::PI;
+
+    #[test]
+    fn new_group() {
+
+        let g = Group::new();
+
+        assert_eq!(g.transform(), matrix::Matrix::identity());
+        assert!(g.is_empty());
+    }
+
+    #[test]
+    fn add_child() {
+
+        let mut g = Group::new();
+        let s = ray::Sphere::unit();
+        let id = s.id();
+
+        g.add_child(s);
+
+        assert!(!g.is_empty());
+        assert_eq!(g.children()[0].id(), id);
+    }
+
+    #[test]
+    fn intersect_empty_group() {
+
+        let g = Group::new();
+        let r = ray::Ray::new(geo::Point3::new(0.0, 0.0, 0.0), geo::Vec3::new(0.0, 0.0, 1.0));
+
+        assert!(g.intersect(&r).is_empty());
+    }
+
+    #[test]
+    fn intersect_nonempty_group() {
+
+        let s1 = ray::Sphere::unit();
+        let mut s2 = ray::Sphere::unit();
+        s2.set_transform(matrix::Matrix::translate(0.0, 0.0,-3.0)).unwrap();
+        let mut s3 = ray::Sphere::unit();
+        s3.set_transform(matrix::Matrix::translate(5.0, 0.0, 0.0)).unwrap();
+
+        let (id1, id2) = (s1.id(), s2.id());
+
+        let mut g = Group::new();
+        g.add_child(s1);
+        g.add_child(s2);
+        g.add_child(s3);
+
+        let r  = ray::Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let xs = g.intersect(&r);
+
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[0].id, id2);
+        assert_eq!(xs[1].id, id2);
+        assert_eq!(xs[2].id, id1);
+        assert_eq!(xs[3].id, id1);
+    }
+
+    #[test]
+    fn intersect_transformed_group() {
+
+        let mut s = ray::Sphere::unit();
+        s.set_transform(matrix::Matrix::translate(5.0, 0.0, 0.0)).unwrap();
+
+        let mut g = Group::new();
+        g.set_transform(matrix::Matrix::scale(2.0, 2.0, 2.0)).unwrap();
+        g.add_child(s);
+
+        let r  = ray::Ray::new(geo::Point3::new(10.0, 0.0,-10.0), geo::Vec3::new(0.0, 0.0, 1.0));
+
+        assert_eq!(g.intersect(&r).len(), 2);
+    }
+
+    #[test]
+    fn singular_group_transform() {
+
+        let mut g  = Group::new();
+        let err    = g.set_transform(matrix::Matrix::scale(1.0, 1.0, 0.0)).unwrap_err();
+
+        assert_eq!(err.kind, "group");
+        assert_eq!(err.id, g.id());
+        assert_eq!(g.transform(), matrix::Matrix::identity());
+    }
+
+    #[test]
+    fn group_bounds() {
+
+        let mut s1 = ray::Sphere::unit();
+        s1.set_transform(matrix::Matrix::translate(2.0, 5.0,-3.0) * matrix::Matrix::scale(2.0, 2.0, 2.0)).unwrap();
+        let mut s2 = ray::Sphere::unit();
+        s2.set_transform(matrix::Matrix::translate(-4.0, 0.0, 0.0)).unwrap();
+
+        let mut g = Group::new();
+        g.add_child(s1);
+        g.add_child(s2);
+
+        assert_eq!(g.bounds().min, geo::Point3::new(-5.0,-1.0,-5.0));
+        assert_eq!(g.bounds().max, geo::Point3::new( 4.0, 7.0, 1.0));
+
+        g.set_transform(matrix::Matrix::translate(1.0, 1.0, 1.0)).unwrap();
+        assert_eq!(g.bounds().min, geo::Point3::new(-4.0, 0.0,-4.0));
+    }
+
+    // builds g1 (rotate_y) > g2 (scale) > sphere (translate) and returns
+    // the outer group alongside the id of the nested sphere
+    fn nested(scale:matrix::Matrix) -> (Group, i32) {
+
+        let mut s = ray::Sphere::unit();
+        s.set_transform(matrix::Matrix::translate(5.0, 0.0, 0.0)).unwrap();
+        let id = s.id();
+
+        let mut g2 = Group::new();
+        g2.set_transform(scale).unwrap();
+        g2.add_child(s);
+
+        let mut g1 = Group::new();
+        g1.set_transform(matrix::Matrix::rotate_y(PI/2.0)).unwrap();
+        g1.add_child(g2);
+
+        (g1, id)
+    }
+
+    fn sphere_in(g:Group, id:i32) -> ray::Sphere {
+        match shape::Shape::from(g).find(id) {
+            Some(shape::Shape::Sphere(s)) => s.clone(),
+            _                             => panic!("sphere not found"),
+        }
+    }
+
+    #[test]
+    fn world_to_object() {
+
+        let (g1, id) = nested(matrix::Matrix::scale(2.0, 2.0, 2.0));
+        let s = sphere_in(g1, id);
+
+        let p = s.world_to_object(geo::Point3::new(-2.0, 0.0,-10.0));
+        assert_eq!(p, geo::Point3::new(0.0, 0.0,-1.0));
+    }
+
+    #[test]
+    fn normal_to_world() {
+
+        let (g1, id) = nested(matrix::Matrix::scale(1.0, 2.0, 3.0));
+        let s = sphere_in(g1, id);
+
+        let r = geo::Float::sqrt(3.0)/3.0;
+        let n = s.normal_to_world(geo::Vec3::new(r, r, r));
+        assert_eq!(n, geo::Vec3::new(0.28571, 0.42857,-0.85714));
+    }
+
+    #[test]
+    fn normal_on_child() {
+
+        let (g1, id) = nested(matrix::Matrix::scale(1.0, 2.0, 3.0));
+        let s = sphere_in(g1, id);
+
+        let n = s.normal_at(geo::Point3::new(1.7321, 1.1547,-5.5774));
+        assert_eq!(n, geo::Vec3::new(0.28570, 0.42854,-0.85716));
+    }
+
+    #[test]
+    fn transform_after_adding_children() {
+
+        // setting the outer transform last must still reach the nested sphere
+        let mut s = ray::Sphere::unit();
+        s.set_transform(matrix::Matrix::translate(5.0, 0.0, 0.0)).unwrap();
+        let id = s.id();
+
+        let mut g2 = Group::new();
+        g2.add_child(s);
+        g2.set_transform(matrix::Matrix::scale(2.0, 2.0, 2.0)).unwrap();
+
+        let mut g1 = Group::new();
+        g1.add_child(g2);
+        g1.set_transform(matrix::Matrix::rotate_y(PI/2.0)).unwrap();
+
+        let s = sphere_in(g1, id);
+        let p = s.world_to_object(geo::Point3::new(-2.0, 0.0,-10.0));
+        assert_eq!(p, geo::Point3::new(0.0, 0.0,-1.0));
+    }
+}
@@ -0,0 +1,391 @@
+use crate::geo;
+use crate::ray;
+use crate::rng;
+use crate::color;
+use crate::light;
+use crate::scene;
+use crate::sample;
+
+// paths shorter than this are never cut short by russian roulette
+const ROULETTE_DEPTH:u32 = 3;
+// and a path always survives it with at least this chance, so dim paths are
+// not all killed off, nor bright ones kept forever
+const MIN_SURVIVAL:geo::Float = 0.05;
+const MAX_SURVIVAL:geo::Float = 0.95;
+
+// How the colour seen along a camera ray is worked out, chosen per render.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum Integrator {
+    // the book's Phong lighting from every light that is not in shadow, with
+    // the material's ambient term standing in for indirect light. Emissive
+    // surfaces glow but light nothing else, and the environment is only seen
+    // where rays miss.
+    Phong,
+    // Monte Carlo path tracing. Every surface takes light straight from each
+    // light (next event estimation) and then bounces the path on in a
+    // direction drawn from its material's brdf, up to max_depth surfaces.
+    // Emissive surfaces light others only when a bounce happens to hit them,
+    // so small bright ones are noisy. The environment is sampled as a light
+    // too, weighted against bounces that reach it by multiple importance
+    // sampling. Ambient is not used.
+    //
+    // Point lights fall off with the square of the distance here, their
+    // intensity is the power per unit solid angle.
+    Path { max_depth:u32 },
+    // Ambient occlusion, for compositing. Casts `rays` cosine weighted rays
+    // over the hemisphere at each hit and gives the fraction that get further
+    // than max_distance as a grey level. Rays that miss everything are white.
+    Occlusion { rays:u32, max_distance:geo::Float },
+}
+
+impl Integrator {
+
+    // the colour arriving back along r, the environment's if it misses
+    // everything
+    pub fn radiance(&self, scene:&scene::Scene, r:&ray::Ray, rng:&mut rng::Pcg32) -> color::Color {
+        match scene.hit(r) {
+            Some(hit) => self.shade(scene, r, &hit, rng),
+            None      => self.miss(scene, r),
+        }
+    }
+
+    // the colour of a ray that hit nothing
+    pub fn miss(&self, scene:&scene::Scene, r:&ray::Ray) -> color::Color {
+        match *self {
+            Integrator::Occlusion { .. } => color::Color::new(1.0, 1.0, 1.0),
+            _                            => scene.environment.radiance(r.dir.norm()),
+        }
+    }
+
+    // the colour arriving back along r from the surface it hit, for callers
+    // that found the hit themselves
+    pub fn shade(&self, scene:&scene::Scene, r:&ray::Ray, hit:&scene::Hit, rng:&mut rng::Pcg32) -> color::Color {
+        match *self {
+            Integrator::Phong               => phong(scene, r, hit),
+            Integrator::Path { max_depth }  => path(scene, r, hit, max_depth, rng),
+            Integrator::Occlusion { rays, max_distance } => occlusion(scene, hit, rays, max_distance, rng),
+        }
+    }
+}
+
+fn black() -> color::Color {
+    color::Color::new(0.0, 0.0, 0.0)
+}
+
+fn phong(scene:&scene::Scene, r:&ray::Ray, hit:&scene::Hit) -> color::Color {
+
+    let eye = -(r.dir).norm();
+
+    scene.lights.iter().fold(hit.material.emission, |c, &l| {
+
+        if scene.occluded(hit.over_point(), l.position) {
+            c + hit.material.surface_color()*l.intensity*hit.material.ambient
+        } else {
+            c + light::lighting(hit.material, l, hit.point, eye, hit.normal)
+        }
+    })
+}
+
+fn path(scene:&scene::Scene, r:&ray::Ray, first:&scene::Hit, max_depth:u32, rng:&mut rng::Pcg32) -> color::Color {
+
+    let mut total      = black();
+    let mut throughput = color::Color::new(1.0, 1.0, 1.0);
+    let mut hit        = *first;
+    let mut wo         = -(r.dir).norm();
+
+    for depth in 0..max_depth {
+
+        // every hit but the first was reached by sampling the brdf, so this
+        // is the only way light from emissive surfaces is counted
+        total = total + throughput*(hit.material.emission + direct(scene, &hit, wo));
+
+        // on the last surface no bounce can find the environment, so the
+        // light sample has to account for all of it
+        let last = depth + 1 == max_depth;
+        total = total + throughput*environment(scene, &hit, wo, last, rng);
+
+        if last {
+            break;
+        }
+
+        let (wi, weight) = match hit.material.sample(hit.normal, wo, rng) {
+            Some(s) => s,
+            None    => break,
+        };
+        let pdf = hit.material.pdf(hit.normal, wo, wi);
+
+        throughput = throughput*weight;
+
+        if depth + 1 >= ROULETTE_DEPTH {
+
+            let survival = throughput.red.max(throughput.green).max(throughput.blue)
+                                         .clamp(MIN_SURVIVAL, MAX_SURVIVAL);
+
+            if rng.next_float() >= survival {
+                break;
+            }
+
+            throughput = throughput*(1.0/survival);
+        }
+
+        hit = match scene.hit(&ray::Ray::new(hit.over_point(), wi)) {
+            Some(h) => h,
+            None    => {
+                let env = &scene.environment;
+                total = total + throughput*env.radiance(wi)*power_heuristic(pdf, env.pdf(wi));
+                break;
+            }
+        };
+        wo = -wi;
+    }
+
+    total
+}
+
+fn occlusion(scene:&scene::Scene, hit:&scene::Hit, rays:u32, max_distance:geo::Float, rng:&mut rng::Pcg32) -> color::Color {
+
+    if rays == 0 {
+        return color::Color::new(1.0, 1.0, 1.0);
+    }
+
+    let open = (0..rays).filter(|_| {
+        let dir = sample::cosine_hemisphere(hit.normal, rng);
+        !scene.occluded(hit.over_point(), hit.over_point() + dir*max_distance)
+    }).count();
+
+    let v = open as geo::Float/rays as geo::Float;
+    color::Color::new(v, v, v)
+}
+
+// light reflected toward wo from one direction drawn from the environment,
+// weighted against the chance of a bounce finding it unless this is the last surface
+fn environment(scene:&scene::Scene, hit:&scene::Hit, wo:geo::Vec3, last:bool, rng:&mut rng::Pcg32) -> color::Color {
+
+    let (wi, radiance, pdf) = match scene.environment.sample(rng) {
+        Some(s) => s,
+        None    => return black(),
+    };
+
+    let cos = wi.dot(hit.normal);
+
+    if cos <= 0.0 || scene.blocked(&ray::Ray::new(hit.over_point(), wi)) {
+        return black();
+    }
+
+    let weight = if last { 1.0 } else { power_heuristic(pdf, hit.material.pdf(hit.normal, wo, wi)) };
+
+    hit.material.brdf(hit.normal, wo, wi)*radiance*(cos*weight/pdf)
+}
+
+// the share of a sample drawn with pdf a that another strategy drawing with
+// pdf b would also have made
+fn power_heuristic(a:geo::Float, b:geo::Float) -> geo::Float {
+
+    if a <= 0.0 {
+        return 0.0;
+    }
+
+    a*a/(a*a + b*b)
+}
+
+// light reflected toward wo straight from every light that can see the hit
+fn direct(scene:&scene::Scene, hit:&scene::Hit, wo:geo::Vec3) -> color::Color {
+
+    scene.lights.iter().fold(black(), |c, l| {
+
+        let to  = l.position - hit.point;
+        let wi  = to.norm();
+        let cos = wi.dot(hit.normal);
+
+        if cos <= 0.0 || scene.occluded(hit.over_point(), l.position) {
+            return c;
+        }
+
+        c + hit.material.brdf(hit.normal, wo, wi)*l.intensity*(cos/to.dot(to))
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape;
+    use crate::matrix;
+    use crate::environment;
+    use geo::consts::PI;
+
+    fn lit_sphere(intensity:geo::Float) -> scene::Scene {
+
+        let light = light::Light::point(color::Color::new(intensity, intensity, intensity), geo::Point3::new(0.0, 0.0, -10.0));
+        scene::Scene::new(vec![shape::Shape::from(ray::Sphere::unit())], vec![light])
+    }
+
+    #[test]
+    fn phong_matches_lighting() {
+
+        let scene = lit_sphere(1.0);
+        let r     = ray::Ray::new(geo::Point3::new(0.0, 0.0, -5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let mut rng = rng::Pcg32::new(0, 0);
+
+        assert_eq!(Integrator::Phong.radiance(&scene, &r, &mut rng), color::Color::new(1.9, 1.9, 1.9));
+
+        // from inside, the light is behind the far wall and only ambient is left
+        let r = ray::Ray::new(geo::Point3::origin(), geo::Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(Integrator::Phong.radiance(&scene, &r, &mut rng), color::Color::new(0.1, 0.1, 0.1));
+
+        let r = ray::Ray::new(geo::Point3::new(0.0, 5.0, -5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(Integrator::Phong.radiance(&scene, &r, &mut rng), color::Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn path_direct_light() {
+
+        let scene = lit_sphere(100.0);
+        let r     = ray::Ray::new(geo::Point3::new(0.0, 0.0, -5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let mut rng = rng::Pcg32::new(0, 0);
+
+        // facing the light 9 units away, with the default 0.9 diffuse white
+        let v = 0.9/PI*100.0/81.0;
+        assert_eq!(Integrator::Path {max_depth:1}.radiance(&scene, &r, &mut rng), color::Color::new(v, v, v));
+    }
+
+    #[test]
+    fn indirect_light_reaches_shadows() {
+
+        // a red ball over a huge white floor, lit from above, looked at from
+        // underneath where no light reaches it directly
+        let mut ball = ray::Sphere::unit();
+        ball.material.color = color::Color::new(1.0, 0.0, 0.0);
+
+        let floor = ray::Sphere::with_transform(matrix::Matrix::translate(0.0, -102.0, 0.0)*matrix::Matrix::scale(100.0, 100.0, 100.0)).unwrap();
+        let light = light::Light::point(color::Color::new(50.0, 50.0, 50.0), geo::Point3::new(0.0, 10.0, 0.0));
+        let scene = scene::Scene::new(vec![shape::Shape::from(ball), shape::Shape::from(floor)], vec![light]);
+
+        let r = ray::Ray::new(geo::Point3::new(0.0, -1.5, 0.0), geo::Vec3::new(0.0, 1.0, 0.0));
+
+        let mean = |integrator:Integrator| {
+            (0..500).fold(black(), |c, s| c + integrator.radiance(&scene, &r, &mut rng::Pcg32::for_sample(1, 0, 0, s)))*(1.0/500.0)
+        };
+
+        assert_eq!(mean(Integrator::Path {max_depth:1}), black());
+        // where Phong only has its ambient guess
+        assert_eq!(Integrator::Phong.radiance(&scene, &r, &mut rng::Pcg32::new(0, 0)), color::Color::new(5.0, 0.0, 0.0));
+
+        // light bounced off the floor, tinted by the ball's own colour
+        let bounced = mean(Integrator::Path {max_depth:8});
+        assert!(bounced.red > 0.001);
+        assert_eq!(bounced.green, 0.0);
+        assert_eq!(bounced.blue, 0.0);
+    }
+
+    #[test]
+    fn emissive_surfaces() {
+
+        let mut lamp = ray::Sphere::unit();
+        lamp.material.emission = color::Color::new(2.0, 1.0, 0.5);
+
+        let floor = ray::Sphere::with_transform(matrix::Matrix::translate(0.0, -102.0, 0.0)*matrix::Matrix::scale(100.0, 100.0, 100.0)).unwrap();
+        let scene = scene::Scene::new(vec![shape::Shape::from(lamp), shape::Shape::from(floor)], vec![]);
+        let mut rng = rng::Pcg32::new(0, 0);
+
+        // seen directly
+        let at_lamp = ray::Ray::new(geo::Point3::new(0.0, 0.0, -5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let glow    = Integrator::Phong.radiance(&scene, &at_lamp, &mut rng);
+        assert_eq!(glow, color::Color::new(2.0, 1.0, 0.5));
+        assert_eq!(Integrator::Path {max_depth:1}.radiance(&scene, &at_lamp, &mut rng), glow);
+
+        // and lighting the floor under it, only when path traced
+        let at_floor = ray::Ray::new(geo::Point3::new(0.5, 0.0, -3.0), geo::Vec3::new(0.0, -1.0, 1.0));
+        assert_eq!(Integrator::Phong.radiance(&scene, &at_floor, &mut rng), black());
+
+        let lit = (0..200).fold(black(), |c, s| c + Integrator::Path {max_depth:2}.radiance(&scene, &at_floor, &mut rng::Pcg32::for_sample(0, 0, 0, s)));
+        assert!(lit.red > 0.0 && lit.red > lit.green && lit.green > lit.blue);
+    }
+
+    #[test]
+    fn furnace() {
+
+        // inside a glowing sphere that reflects half the light reaching it,
+        // the light bounces on forever and adds up to twice the emission
+        let mut shell = ray::Sphere::unit();
+        shell.material.diffuse  = 0.5;
+        shell.material.emission = color::Color::new(1.0, 1.0, 1.0);
+
+        let scene = scene::Scene::new(vec![shape::Shape::from(shell)], vec![]);
+        let r     = ray::Ray::new(geo::Point3::origin(), geo::Vec3::new(0.3, 0.4, 0.5));
+        let n     = 4000;
+
+        let mean = (0..n).fold(0.0, |m, s| {
+            m + Integrator::Path {max_depth:100}.radiance(&scene, &r, &mut rng::Pcg32::for_sample(2, 0, 0, s)).red/n as geo::Float
+        });
+
+        assert!((mean - 2.0).abs() < 0.05, "{}", mean);
+    }
+
+    #[test]
+    fn lit_by_the_environment() {
+
+        // a ball under an even white sky reflects its albedo of it wherever
+        // it is seen, the sky and the bounces have to add up to that exactly
+        let mut scene = scene::Scene::new(vec![shape::Shape::from(ray::Sphere::unit())], vec![]);
+        scene.environment = environment::Environment::Solid(color::Color::new(1.0, 1.0, 1.0));
+
+        let r = ray::Ray::new(geo::Point3::new(0.2, 0.3, -5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let n = 4000;
+
+        for &max_depth in [1, 4].iter() {
+            let mean = (0..n).fold(0.0, |m, s| {
+                m + Integrator::Path {max_depth}.radiance(&scene, &r, &mut rng::Pcg32::for_sample(3, 0, 0, s)).red/n as geo::Float
+            });
+            assert!((mean - 0.9).abs() < 0.03, "depth {}: {}", max_depth, mean);
+        }
+
+        // and rays that miss see the sky
+        let miss = ray::Ray::new(geo::Point3::new(0.0, 5.0, -5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(Integrator::Phong.radiance(&scene, &miss, &mut rng::Pcg32::new(0, 0)), color::Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn ambient_occlusion() {
+
+        // a ball resting on a huge floor
+        let ball  = ray::Sphere::unit();
+        let floor = ray::Sphere::with_transform(matrix::Matrix::translate(0.0, -101.0, 0.0)*matrix::Matrix::scale(100.0, 100.0, 100.0)).unwrap();
+        let scene = scene::Scene::new(vec![shape::Shape::from(ball), shape::Shape::from(floor)], vec![]);
+        let mut rng = rng::Pcg32::new(8, 0);
+
+        let ao   = |d:geo::Float| Integrator::Occlusion {rays:2000, max_distance:d};
+        let down = geo::Vec3::new(0.0, -1.0, 0.0);
+
+        // the top of the ball sees nothing but sky
+        let top = ray::Ray::new(geo::Point3::new(0.0, 5.0, 0.0), down);
+        assert_eq!(ao(10.0).radiance(&scene, &top, &mut rng), color::Color::new(1.0, 1.0, 1.0));
+
+        // the floor next to the ball is partly covered by it, unless the rays
+        // stop short of it
+        let beside = ray::Ray::new(geo::Point3::new(1.2, 5.0, 0.0), down);
+        let v = ao(10.0).radiance(&scene, &beside, &mut rng).red;
+        assert!(v > 0.3 && v < 0.9, "{}", v);
+        assert_eq!(ao(0.01).radiance(&scene, &beside, &mut rng), color::Color::new(1.0, 1.0, 1.0));
+
+        // inside the ball nothing is open, and misses are white
+        let inside = ray::Ray::new(geo::Point3::origin(), down);
+        assert_eq!(ao(10.0).radiance(&scene, &inside, &mut rng), color::Color::new(0.0, 0.0, 0.0));
+
+        let miss = ray::Ray::new(geo::Point3::new(0.0, 5.0, 0.0), geo::Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(ao(10.0).radiance(&scene, &miss, &mut rng), color::Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn path_is_reproducible() {
+
+        let scene = lit_sphere(100.0);
+        let r     = ray::Ray::new(geo::Point3::new(0.3, 0.2, -5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+        let path  = Integrator::Path {max_depth:6};
+
+        let a = path.radiance(&scene, &r, &mut rng::Pcg32::for_sample(5, 1, 2, 3));
+        let b = path.radiance(&scene, &r, &mut rng::Pcg32::for_sample(5, 1, 2, 3));
+
+        assert_eq!(a.red.to_bits(), b.red.to_bits());
+    }
+}
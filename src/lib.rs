@@ -0,0 +1,38 @@
+// The ray tracer's building blocks, put together into a render by main.rs.
+
+pub mod geo;
+pub mod ray;
+pub mod rng;
+pub mod sample;
+pub mod microfacet;
+pub mod scene;
+pub mod environment;
+pub mod integrator;
+pub mod aov;
+pub mod simd;
+pub mod packet;
+pub mod render;
+pub mod progress;
+pub mod progressive;
+pub mod checkpoint;
+pub mod bvh;
+pub mod csg;
+pub mod group;
+pub mod shape;
+pub mod bounds;
+pub mod light;
+pub mod color;
+pub mod canvas;
+pub mod matrix;
+pub mod decompose;
+pub mod quaternion;
+pub mod material;
+pub mod projectile;
+
+// where tests write the files they read back, out of the tree and apart from
+// any other test run going on at the same time
+#[cfg(test)]
+pub(crate) fn temp_path(name:&str) -> String {
+    let file = format!("ray_tracing_{}_{}", std::process::id(), name);
+    std::env::temp_dir().join(file).to_string_lossy().into_owned()
+}
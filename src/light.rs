@@ -1,20 +1,63 @@
 use crate::material;
 use crate::color;
 use crate::geo;
+use crate::microfacet;
+use geo::consts::PI;
 
 #[derive(Debug,Copy,Clone)]
 pub struct Light {
     pub intensity:color::Color,
-    pub position:geo::Geo,
+    pub position:geo::Point3,
 }
 
 impl Light {
-   pub fn point(intensity:color::Color, position:geo::Geo) -> Light {
-       Light {intensity:intensity, position:position}
+   pub fn point(intensity:color::Color, position:geo::Point3) -> Light {
+       Light {intensity, position}
    }
 }
 
-pub fn lighting(material:material::Material, light:Light, point:geo::Geo, eyev:geo::Geo, norm:geo::Geo) -> color::Color {
+pub fn lighting(material:material::Material, light:Light, point:geo::Point3, eyev:geo::Vec3, norm:geo::Vec3) -> color::Color {
+    match material.shading {
+        material::Shading::Phong        => phong(material, light, point, eyev, norm),
+        material::Shading::BlinnPhong   => phong(material, light, point, eyev, norm),
+        material::Shading::OrenNayar(s) => oren_nayar(material, s, light, point, eyev, norm),
+        material::Shading::Ggx(g)       => ggx(material, g, light, point, eyev, norm),
+    }
+}
+
+// Phong's ambient and diffuse terms with the diffuse scaled for roughness, no
+// specular highlight
+fn oren_nayar(material:material::Material, sigma:geo::Float, light:Light, point:geo::Point3, eyev:geo::Vec3, norm:geo::Vec3) -> color::Color {
+
+    let effective_color = material.color*light.intensity;
+    let ambient = effective_color*material.ambient;
+    let lightv  = (light.position - point).norm();
+    let cos     = lightv.dot(norm);
+
+    if cos <= 0.0 {
+        return ambient;
+    }
+
+    ambient + effective_color*(material.diffuse*cos*material::oren_nayar(sigma, norm, eyev, lightv))
+}
+
+// Like Phong's diffuse term the light arrives undimmed by distance, and the
+// brdf is scaled by pi so a white lambertian surface facing the light reflects
+// exactly its colour.
+fn ggx(material:material::Material, g:microfacet::Ggx, light:Light, point:geo::Point3, eyev:geo::Vec3, norm:geo::Vec3) -> color::Color {
+
+    let ambient = g.base_color*light.intensity*material.ambient;
+    let lightv  = (light.position - point).norm();
+    let cos     = lightv.dot(norm);
+
+    if cos <= 0.0 {
+        return ambient;
+    }
+
+    ambient + g.eval(norm, eyev, lightv)*light.intensity*(PI*cos)
+}
+
+fn phong(material:material::Material, light:Light, point:geo::Point3, eyev:geo::Vec3, norm:geo::Vec3) -> color::Color {
 
     let black = color::Color::new(0.0, 0.0, 0.0);
     // surface color combined with light
@@ -33,8 +76,12 @@ pub fn lighting(material:material::Material, light:Light, point:geo::Geo, eyev:g
         return ambient + diffuse + specular;
     } else {
         diffuse             = effective_color*material.diffuse*light_dot_normal;
-        let reflectv        = (-lightv).reflect(norm);
-        let reflect_dot_eye = reflectv.dot(eyev);
+        // Blinn's version measures the highlight with the vector halfway
+        // between the light and the eye, which keeps its shape at grazing angles
+        let reflect_dot_eye = match material.shading {
+            material::Shading::BlinnPhong => (lightv + eyev).norm().dot(norm),
+            _                             => (-lightv).reflect(norm).dot(eyev),
+        };
 
         if reflect_dot_eye <= 0.0 {
             specular = black;
@@ -45,17 +92,17 @@ pub fn lighting(material:material::Material, light:Light, point:geo::Geo, eyev:g
         }
     }
 
-    return ambient + diffuse + specular;
+    ambient + diffuse + specular
 }
 
 #[test]
 fn light_struct() {
 
     let i = color::Color::new(1.0, 1.0, 1.0);
-    let p = geo::Geo::point(0.0, 0.0, 0.0);
+    let p = geo::Point3::new(0.0, 0.0, 0.0);
     let l = Light::point(i, p);
 
-    assert_eq!(l.position,geo::Geo::point(0.0, 0.0, 0.0));
+    assert_eq!(l.position,geo::Point3::new(0.0, 0.0, 0.0));
     assert_eq!(l.intensity, color::Color::new(1.0, 1.0, 1.0));
 
 }
@@ -64,12 +111,12 @@ fn light_struct() {
 fn light_eye_surface() {
 
     let m    = material::Material::default();
-    let p    = geo::Geo::point(0.0, 0.0, 0.0);
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
 
-    let eyev = geo::Geo::vector(0.0, 0.0, -1.0);
-    let norm = geo::Geo::vector(0.0, 0.0, -1.0);
+    let eyev = geo::Vec3::new(0.0, 0.0, -1.0);
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
 
-    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Geo::point(0.0, 0.0, -10.0));
+    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, -10.0));
     let result = lighting(m, light, p, eyev, norm);
     assert_eq!(result,color::Color::new(1.9, 1.9, 1.9));
 }
@@ -78,40 +125,42 @@ fn light_eye_surface() {
 fn light_eye_45_surface() {
 
     let m    = material::Material::default();
-    let p    = geo::Geo::point(0.0, 0.0, 0.0);
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
 
-    let eyev = geo::Geo::vector(0.0, 1.0/(2.0f64).sqrt(), 1.0/(2.0f64).sqrt());
-    let norm = geo::Geo::vector(0.0, 0.0, -1.0);
+    let eyev = geo::Vec3::new(0.0, 1.0/geo::Float::sqrt(2.0), 1.0/geo::Float::sqrt(2.0));
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
 
-    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Geo::point(0.0, 0.0, -10.0));
+    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, -10.0));
     let result = lighting(m, light, p, eyev, norm);
     assert_eq!(result,color::Color::new(1.0, 1.0, 1.0));
 }
 
 #[test]
+#[allow(clippy::excessive_precision)]
 fn light_45_eye_surface() {
 
     let m    = material::Material::default();
-    let p    = geo::Geo::point(0.0, 0.0, 0.0);
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
 
-    let eyev = geo::Geo::vector(0.0, 0.0, -1.0);
-    let norm = geo::Geo::vector(0.0, 0.0, -1.0);
+    let eyev = geo::Vec3::new(0.0, 0.0, -1.0);
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
 
-    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Geo::point(0.0, 10.0, -10.0));
+    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 10.0, -10.0));
     let result = lighting(m, light, p, eyev, norm);
     assert_eq!(result,color::Color::new(0.736396103, 0.736396103, 0.736396103));
 }
 
 #[test]
+#[allow(clippy::excessive_precision)]
 fn light_eye_reflect_off_surface() {
 
     let m    = material::Material::default();
-    let p    = geo::Geo::point(0.0, 0.0, 0.0);
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
 
-    let eyev = geo::Geo::vector(0.0, -1.0/(2.0f64).sqrt(), -1.0/(2.0f64).sqrt());
-    let norm = geo::Geo::vector(0.0, 0.0, -1.0);
+    let eyev = geo::Vec3::new(0.0, -1.0/geo::Float::sqrt(2.0), -1.0/geo::Float::sqrt(2.0));
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
 
-    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Geo::point(0.0, 10.0, -10.0));
+    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 10.0, -10.0));
     let result = lighting(m, light, p, eyev, norm);
     assert_eq!(result,color::Color::new(1.636396103, 1.636396103, 1.636396103));
 }
@@ -121,12 +170,75 @@ fn light_eye_reflect_off_surface() {
 fn light_behind_surface() {
 
     let m    = material::Material::default();
-    let p    = geo::Geo::point(0.0, 0.0, 0.0);
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
 
-    let eyev = geo::Geo::vector(0.0, 0.0, -1.0);
-    let norm = geo::Geo::vector(0.0, 0.0, -1.0);
+    let eyev = geo::Vec3::new(0.0, 0.0, -1.0);
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
 
-    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Geo::point(0.0, 0.0, 10.0));
+    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, 10.0));
     let result = lighting(m, light, p, eyev, norm);
     assert_eq!(result,color::Color::new(0.1, 0.1, 0.1));
 }
+
+#[test]
+fn light_ggx_surface() {
+
+    let m    = material::Material::ggx(color::Color::new(1.0, 0.5, 0.25), 1.0, 0.3);
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
+    let eyev = geo::Vec3::new(0.0, 0.0, -1.0);
+
+    // a metal seen straight on with the light behind the eye shows a bright
+    // highlight tinted by its base colour
+    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, -10.0));
+    let result = lighting(m, light, p, eyev, norm);
+    assert!(result.red > 1.0);
+    assert!((result.green/result.red - 0.5).abs() < 0.01);
+
+    // and only ambient from behind
+    let light  = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, 10.0));
+    let result = lighting(m, light, p, eyev, norm);
+    assert_eq!(result, color::Color::new(0.1, 0.05, 0.025));
+}
+
+#[test]
+fn light_blinn_phong_surface() {
+
+    let m    = material::Material {shininess:10.0, ..material::Material::default()};
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
+    let eyev = geo::Vec3::new(0.0, 0.0, -1.0);
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
+
+    // straight on both models give the full highlight
+    let light = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, -10.0));
+    let blinn = material::Material {shading:material::Shading::BlinnPhong, ..m};
+    assert_eq!(lighting(blinn, light, p, eyev, norm), lighting(m, light, p, eyev, norm));
+
+    // 45 degrees off, the half vector is only 22.5 degrees from the normal
+    let light = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 10.0, -10.0));
+    let half  = (geo::consts::PI/8.0).cos().powf(10.0);
+    let diff  = 0.9/geo::Float::sqrt(2.0);
+    assert_eq!(lighting(blinn, light, p, eyev, norm), color::Color::new(0.1 + diff + 0.9*half, 0.1 + diff + 0.9*half, 0.1 + diff + 0.9*half));
+}
+
+#[test]
+fn light_oren_nayar_surface() {
+
+    let lambert = material::Material {shading:material::Shading::OrenNayar(0.0), ..material::Material::default()};
+    let rough   = material::Material {shading:material::Shading::OrenNayar(0.5), ..material::Material::default()};
+    let p    = geo::Point3::new(0.0, 0.0, 0.0);
+    let norm = geo::Vec3::new(0.0, 0.0, -1.0);
+
+    // a smooth surface is lambertian, without Phong's highlight
+    let light = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, -10.0));
+    let eyev  = geo::Vec3::new(0.0, 0.0, -1.0);
+    assert_eq!(lighting(lambert, light, p, eyev, norm), color::Color::new(1.0, 1.0, 1.0));
+
+    // a rough one is darker head on but brighter lit and seen from a low angle
+    // on the same side, the flattened look of clay or the moon
+    assert!(lighting(rough, light, p, eyev, norm).red < 1.0);
+
+    let light = Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 10.0, -5.0));
+    let eyev  = geo::Vec3::new(0.0, 2.0, -1.0).norm();
+    assert!(lighting(rough, light, p, eyev, norm).red > lighting(lambert, light, p, eyev, norm).red);
+}
@@ -1,86 +1,255 @@
-mod geo;
-mod ray;
-mod light;
-mod color;
-mod canvas;
-mod matrix;
-mod material;
-mod projectile;
-use rayon::prelude::*;
-use std::time::{Instant};
+use ray_tracing::{geo, ray, rng, scene, environment, integrator, aov, packet, render, progress, progressive, checkpoint, bvh, shape, light, color};
+
+use std::path::Path;
+use std::time::{Duration,Instant};
+use std::sync::atomic::{AtomicUsize,Ordering};
+
+// how often a render with a checkpoint file saves its progress
+const CHECKPOINT_INTERVAL:Duration = Duration::from_secs(60);
+
+// the nth command line argument, None if it was not given or is empty so
+// later ones can be given without it
+fn text_arg(n:usize) -> Option<String> {
+    std::env::args().nth(n).filter(|a| !a.is_empty())
+}
+
+// the nth command line argument parsed, None if it was not given
+fn arg<T:std::str::FromStr>(n:usize, what:&str) -> Option<T> {
+
+    let a = text_arg(n)?;
+
+    match a.parse() {
+        Ok(v)  => Some(v),
+        Err(_) => usage(n, what, &a),
+    }
+}
+
+fn usage(n:usize, what:&str, given:&str) -> ! {
+    eprintln!("argument {} should be {}, not {}", n, what, given);
+    std::process::exit(2);
+}
+
+// "ao", "ao:rays" or "ao:rays:distance" as an ambient occlusion integrator,
+// 16 rays reaching 2 units unless told otherwise. None if it is not one or
+// asks for no rays or a distance that is not positive.
+fn occlusion(spec:&str) -> Option<integrator::Integrator> {
+
+    let mut parts = spec.split(':');
+
+    if parts.next() != Some("ao") {
+        return None;
+    }
+
+    let rays:u32                = parts.next().map_or(Some(16), |r| r.parse().ok())?;
+    let max_distance:geo::Float = parts.next().map_or(Some(2.0), |d| d.parse().ok())?;
+
+    if parts.next().is_some() || rays == 0 || !(max_distance > 0.0 && max_distance.is_finite()) {
+        return None;
+    }
+
+    Some(integrator::Integrator::Occlusion {rays, max_distance})
+}
+
+// a checkpoint that cannot be resumed from is left alone rather than
+// overwritten by a fresh render
+fn fail(path:&str, why:&str) -> ! {
+    eprintln!("{}: {}", path, why);
+    std::process::exit(1);
+}
 
 fn main() {
 
-    let ray_origin    = geo::Geo::point(0.0, 0.0, -5.0);
+    let ray_origin    = geo::Point3::new(0.0, 0.0, -5.0);
     let wall_z        = 10.0;
     let wall_size     = 10.0;
     let canvas_pixels = 720;
-    let pixel_size    = wall_size/(canvas_pixels as f64);
+    let pixel_size    = wall_size/(canvas_pixels as geo::Float);
     let half          = wall_size/2.0; 
 
-    let mut image = canvas::Canvas::new(canvas_pixels,canvas_pixels);
     let mut shape = ray::Sphere::unit();
-    //shape.set_transform(matrix::Matrix::shear(-1.0, 0.0, 0.0, 0.0, 0.0, 0.5));
+    //shape.set_transform(matrix::Matrix::shear(-1.0, 0.0, 0.0, 0.0, 0.0, 0.5)).unwrap();
     shape.material.color = color::Color::new(0.3, 0.3, 1.0);
 
-    let light_position = geo::Geo::point(-10.0, 10.0, -10.0);
+    let light_position = geo::Point3::new(-10.0, 10.0, -10.0);
     let light_color    = color::Color::new(1.0, 1.0, 1.0);
-    let light          = light::Light::point(light_color, light_position);
-
-    
 
     println!("         STARTING RENDER");
     println!("==================================");
     let now = Instant::now();
 
-    // NEW RAYON METHOD
-    let mut idx:Vec<(usize,usize)> = Vec::new();
+    // the thread count can be given as the first argument, all cores otherwise,
+    // a time limit in seconds as the second, the samples per pixel as the third
+    // and the random seed as the fourth. Giving a fifth, the most surfaces a
+    // path may bounce off, path traces the image instead of using Phong, or
+    // "ao:rays:distance" renders an ambient occlusion pass, casting that many
+    // rays at each hit that count as blocked within that distance. Either
+    // can be left off, as in "ao" or "ao:32". A sixth names a .hdr or .pfm
+    // environment map to surround the scene with, and with a seventh the
+    // depth, normal, albedo, id and hit count buffers are saved as pfm files
+    // starting with it. An eighth names a checkpoint file the render saves its
+    // progress to every so often and picks up from if it is already there.
+    // An empty argument counts as not given.
+    let time_limit = arg::<f64>(2, "a number of seconds").map(|s| {
+        Duration::try_from_secs_f64(s).unwrap_or_else(|_| usage(2, "a number of seconds", &s.to_string()))
+    });
+
+    let mut settings = render::Settings {
+        threads: arg(1, "a thread count").unwrap_or(0),
+        time_limit,
+        seed: arg(4, "a whole number").unwrap_or(0),
+        ..render::Settings::default()
+    };
+    let samples = arg(3, "a sample count");
+    let shading = match text_arg(5).as_deref() {
+        Some(ao) if ao.starts_with("ao") => occlusion(ao)
+            .unwrap_or_else(|| usage(5, "ao:rays:distance with at least 1 ray and a positive distance", ao)),
+        Some(d)    => match arg(5, "a path depth or ao") {
+            Some(0) | None => usage(5, "a path depth of at least 1", d),
+            Some(max_depth) => integrator::Integrator::Path {max_depth},
+        },
+        None       => integrator::Integrator::Phong,
+    };
+
+    // path traced point lights fall off with distance, so the light is made
+    // brighter to give the sphere about the same brightness as under Phong
+    let to_light = light_position - geo::Point3::origin();
+    let light    = match shading {
+        integrator::Integrator::Phong => light::Light::point(light_color, light_position),
+        _ => light::Light::point(light_color*(geo::consts::PI*to_light.dot(to_light)), light_position),
+    };
 
-    for y in 0..canvas_pixels-1 {
-        for x in 0..canvas_pixels-1 {
-            idx.push((x,y));
+    let mut scene = scene::Scene::new(vec![shape::Shape::from(shape)], vec![light]);
+
+    if let Some(path) = text_arg(6) {
+        match environment::EnvMap::load(&path) {
+            Ok(map) => scene.environment = environment::Environment::Map(map),
+            Err(e)  => println!("{}: {}, rendering without it", path, e),
         }
     }
 
-    let ray_vec:Vec<color::Color> = idx.par_iter()
-        .map(|(x,y)| {
+    let num_hits = AtomicUsize::new(0);
+
+    // the ray through a point of the wall given in pixels
+    let wall_ray = |x:geo::Float, y:geo::Float| {
 
-            let world_y = half - pixel_size*(*y as f64);
-            let world_x = -half + pixel_size*(*x as f64);
+        let world_y = half - pixel_size*y;
+        let world_x = -half + pixel_size*x;
 
-            let position = geo::Geo::point(world_x, world_y, wall_z);
-            let r = ray::Ray::new(ray_origin, position-ray_origin);
-            let eye = -(r.dir).norm();
+        let position = geo::Point3::new(world_x, world_y, wall_z);
+        ray::Ray::new(ray_origin, position-ray_origin)
+    };
 
-            let xs  = r.intersect(shape.clone());
-            let hit = ray::Isect::hit(xs);
-            if hit.id >= 0 {
+    // pixels are traced in 2x2 packets, tiles with an odd size repeat their
+    // last row or column
+    let mut acc = progressive::Accumulator::new(canvas_pixels, canvas_pixels);
+    let bar     = progress::Bar::new();
 
-                let point = r.position(hit.t);
-                let norm  = shape.normal_at(point);
+    // a checkpoint left by an earlier run is carried on from, with the seed,
+    // tile size and unless told otherwise sample count it was started with.
+    // It has to have been made with the same render mode and environment.
+    let checkpoint  = text_arg(8);
+    let environment = scene.environment.fingerprint();
+    let mut resumed_samples = None;
 
-                return light::lighting(shape.material,light,point,eye,norm);
-            } else {
-                return color::Color::new(0.0, 0.0, 0.0);
+    if let Some(path) = checkpoint.as_deref().filter(|p| Path::new(p).exists()) {
+        match checkpoint::Checkpoint::load(path) {
+            Ok(c) => {
+                if let Err(e) = c.check(canvas_pixels, canvas_pixels, shading, environment) {
+                    fail(path, &e.to_string());
+                }
+                println!("resuming from {} with {} samples per pixel done and seed {}", path, c.acc.min_samples(), c.seed);
+                settings = render::Settings {threads:settings.threads, time_limit:settings.time_limit, ..c.settings()};
+                resumed_samples = Some(c.samples);
+                acc = c.acc;
             }
-        })
-        .collect();
+            Err(e) => fail(path, &e.to_string()),
+        }
+    }
+
+    let samples  = samples.or(resumed_samples).unwrap_or(4);
+    let mut save = checkpoint.as_ref().map(|p| checkpoint::saver(p, CHECKPOINT_INTERVAL, samples, &settings, shading, environment));
+
+    // the time limit covers the buffers below as well as the image
+    let deadline = settings.time_limit.map(|l| Instant::now() + l);
+
+    progressive::render(&mut acc, samples, &settings, &bar, |tile, sample| {
+
+        let (xmax, ymax) = (tile.x + tile.width - 1, tile.y + tile.height - 1);
+        let clamp = |(x,y):(usize,usize)| (x.min(xmax), y.min(ymax));
+
+        for y in (tile.y..tile.y + tile.height).step_by(2) {
+            for x in (tile.x..tile.x + tile.width).step_by(2) {
+
+                let pixels   = [(x,y), (x+1,y), (x,y+1), (x+1,y+1)].map(clamp);
+                let mut rngs = pixels.map(|(x,y)| rng::Pcg32::for_sample(settings.seed, x, y, sample));
+                // each sample goes through a random point of the pixel, drawn
+                // from the pixel's own generator so the image does not depend
+                // on the schedule
+                let rays     = [0, 1, 2, 3].map(|i| wall_ray(pixels[i].0 as geo::Float + rngs[i].next_float(),
+                                                             pixels[i].1 as geo::Float + rngs[i].next_float()));
+                let hits     = scene.bvh().hit_packet(&packet::Packet::new(rays), &mut bvh::Stats::default());
+
+                // on a tile's last odd row or column the clamp repeats a pixel,
+                // its lane is traced along with the rest but only counted and
+                // written once
+                let distinct = |i:usize| !pixels[..i].contains(&pixels[i]);
+
+                for i in (0..4).filter(|&i| distinct(i)) {
+
+                    let (r, (px,py)) = (rays[i], pixels[i]);
+                    tile.rays += 1;
+
+                    match scene.surface(&r, hits[i]) {
+                        Some(hit) => {
+                            num_hits.fetch_add(1, Ordering::Relaxed);
+                            tile.write_pixel(px, py, shading.shade(&scene, &r, &hit, &mut rngs[i]));
+                        }
+                        None => tile.write_pixel(px, py, shading.miss(&scene, &r)),
+                    }
+                }
+            }
+        }
+    }, |acc, sample| if let Some(save) = save.as_mut() { save(acc, sample) });
 
     println!("{} milliseconds elapsed", now.elapsed().as_millis());
-    println!("{} pixels calculated",ray_vec.len());
+    println!("{} of {} samples per pixel calculated",acc.min_samples(),samples);
+
+    if acc.min_samples() < samples {
+        println!("time limit reached, the image has fewer samples than asked for");
+
+        // the saver only saves on its interval until the render is done
+        if let Some(path) = checkpoint.as_deref() {
+            let c = checkpoint::Checkpoint {samples, tile_size:settings.tile_size, seed:settings.seed,
+                                            integrator:shading, environment, acc:acc.clone()};
+            match c.save(path) {
+                Ok(())  => println!("progress saved to {}, run again to carry on", path),
+                Err(e)  => eprintln!("{}: {}", path, e),
+            }
+        }
+    }
+
+    println!("{} hits detected",num_hits.into_inner());
 
-    let num_hits:i32 = idx.iter()
-        .zip(ray_vec.iter())
-        .map(|((x,y),color)| {
+    acc.snapshot().to_ppm("ppm/sphere.ppm");
 
-                image.write_pixel(*x, *y, *color);
-                1
-        })
-        .sum();
+    if let Some(prefix) = text_arg(7) {
 
-    println!("{} hits detected",num_hits);
+        // the image is already rendered, only the buffers are filled here, in
+        // whatever time it left
+        let remaining = render::Settings {time_limit:deadline.map(|d| d.saturating_duration_since(Instant::now())),
+                                          ..settings.clone()};
 
-    image.to_ppm("ppm/sphere.ppm");
+        let mut aovs = aov::Aovs::new(canvas_pixels, canvas_pixels);
+        let mask     = aov::render_buffers(&mut aovs, &scene, &remaining, &bar,
+                                           |x, y| wall_ray(x as geo::Float + 0.5, y as geo::Float + 0.5));
+
+        if mask.is_complete() {
+            aovs.save_all(&prefix);
+            println!("buffers saved to {}_*.pfm", prefix);
+        } else {
+            println!("time limit reached before the buffers were done, they were not saved");
+        }
+    }
     println!("==================================");
     println!("         RENDER COMPLETE");
 
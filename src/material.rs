@@ -1,37 +1,148 @@
+use crate::geo;
+use crate::rng;
 use crate::color;
+use crate::sample;
+use crate::microfacet;
+use geo::consts::PI;
+
+// how a surface reflects the light falling on it
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum Shading {
+    // the book's model, from color, diffuse, specular and shininess. Sampling
+    // integrators see it as a lambertian surface of colour color*diffuse, as
+    // they do Blinn-Phong.
+    Phong,
+    // Phong with the highlight worked out from the half vector
+    BlinnPhong,
+    // rough diffuse surfaces like clay or cloth, from color and diffuse. The
+    // value is the roughness, the standard deviation of the facet angles in
+    // radians, and 0 is lambertian.
+    OrenNayar(geo::Float),
+    // physically based, everything but ambient comes from the Ggx parameters
+    Ggx(microfacet::Ggx),
+}
 
 #[derive(Debug,Copy,Clone)]
 pub struct Material {
     pub color:color::Color,
-    pub ambient:f64,
-    pub diffuse:f64,
-    pub specular:f64,
-    pub shininess:f64,
+    pub ambient:geo::Float,
+    pub diffuse:geo::Float,
+    pub specular:geo::Float,
+    pub shininess:geo::Float,
+    pub shading:Shading,
+    // light given off by the surface itself, seen directly and, by sampling
+    // integrators, falling on everything around it
+    pub emission:color::Color,
 }
 
-impl Material {
+impl Default for Material {
 
-    pub fn default() -> Material {
+    fn default() -> Material {
         Material {
                 color:color::Color::new(1.0, 1.0, 1.0),
-                ambient:0.1f64,
-                diffuse: 0.9f64,
-                specular: 0.9f64,
-                shininess: 200.0f64,    
+                ambient:0.1,
+                diffuse: 0.9,
+                specular: 0.9,
+                shininess: 200.0,    
+                shading:Shading::Phong,
+                emission:color::Color::new(0.0, 0.0, 0.0),
+            }
+    }
+}
+
+impl Material {
+
+    pub fn ggx(base_color:color::Color, metallic:geo::Float, roughness:geo::Float) -> Material {
+        Material {shading:Shading::Ggx(microfacet::Ggx::new(base_color, metallic, roughness)),
+                  ..Material::default()}
+    }
+
+    // the colour the ambient term is tinted with
+    pub fn surface_color(&self) -> color::Color {
+        match self.shading {
+            Shading::Ggx(g) => g.base_color,
+            _               => self.color,
+        }
+    }
+
+    // the brdf for light arriving from wi and leaving toward wo, all unit
+    // vectors with the normal n on the side of wo
+    pub fn brdf(&self, n:geo::Vec3, wo:geo::Vec3, wi:geo::Vec3) -> color::Color {
+        if let Shading::Ggx(g) = self.shading {
+            return g.eval(n, wo, wi);
+        }
+
+        if n.dot(wi) <= 0.0 {
+            return color::Color::new(0.0, 0.0, 0.0);
+        }
+
+        match self.shading {
+            Shading::OrenNayar(s) => self.color*(self.diffuse*oren_nayar(s, n, wo, wi)/PI),
+            _                     => self.color*(self.diffuse/PI),
+        }
+    }
+
+    // density sample draws wi with, over solid angle
+    pub fn pdf(&self, n:geo::Vec3, wo:geo::Vec3, wi:geo::Vec3) -> geo::Float {
+        match self.shading {
+            Shading::Ggx(g) => g.pdf(n, wo, wi),
+            _               => sample::cosine_hemisphere_pdf(n, wi),
+        }
+    }
+
+    // an incoming direction drawn in proportion to how much light the surface
+    // reflects from it toward wo, and brdf * cos / pdf for it
+    pub fn sample(&self, n:geo::Vec3, wo:geo::Vec3, rng:&mut rng::Pcg32) -> Option<(geo::Vec3, color::Color)> {
+        match self.shading {
+            Shading::Ggx(g) => g.sample(n, wo, rng),
+            Shading::OrenNayar(s) => {
+                let wi = sample::cosine_hemisphere(n, rng);
+                Some((wi, self.color*(self.diffuse*oren_nayar(s, n, wo, wi))))
             }
+            _ => Some((sample::cosine_hemisphere(n, rng), self.color*self.diffuse)),
+        }
     }
 }
 
+// How much brighter or darker than lambertian a surface of roughness sigma
+// looks, for light from wi seen from wo, in the qualitative model of Oren and
+// Nayar. Both directions are unit vectors above the unit normal n.
+pub fn oren_nayar(sigma:geo::Float, n:geo::Vec3, wo:geo::Vec3, wi:geo::Vec3) -> geo::Float {
+
+    let s2 = sigma*sigma;
+    let a  = 1.0 - 0.5*s2/(s2 + 0.33);
+    let b  = 0.45*s2/(s2 + 0.09);
+
+    let (cos_i, cos_o) = (n.dot(wi).clamp(0.0, 1.0), n.dot(wo).clamp(0.0, 1.0));
+    let (sin_i, sin_o) = ((1.0 - cos_i*cos_i).sqrt(), (1.0 - cos_o*cos_o).sqrt());
+
+    // cosine of the azimuth between the two, zero if either is along n
+    let (pi, po) = (wi - n*cos_i, wo - n*cos_o);
+    let (li, lo) = (pi.len(), po.len());
+    let cos_phi  = if li > 0.0 && lo > 0.0 { (pi.dot(po)/(li*lo)).max(0.0) } else { 0.0 };
+
+    // sin of the larger angle from the normal times tan of the smaller
+    let sin_tan = if cos_i < cos_o {
+        sin_i*sin_o/cos_o.max(geo::EPSILON)
+    } else {
+        sin_o*sin_i/cos_i.max(geo::EPSILON)
+    };
+
+    a + b*cos_phi*sin_tan
+}
+
 #[test]
 fn material_default() {
 
     let m = Material::default();
 
     assert_eq!(m.color,color::Color::new(1.0, 1.0, 1.0));
-    assert_eq!(m.ambient, 0.1f64 );
-    assert_eq!(m.diffuse, 0.9f64);
-    assert_eq!(m.specular, 0.9f64);
-    assert_eq!(m.shininess, 200.0f64);
+    assert_eq!(m.ambient, 0.1 );
+    assert_eq!(m.diffuse, 0.9);
+    assert_eq!(m.specular, 0.9);
+    assert_eq!(m.shininess, 200.0);
+    assert_eq!(m.shading, Shading::Phong);
+    assert_eq!(m.emission, color::Color::new(0.0, 0.0, 0.0));
 
 }
 
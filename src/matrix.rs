@@ -1,261 +1,522 @@
-use std::f64::consts::PI;
+#[cfg(test)]
+use geo::consts::PI;
 use std::ops;
 use std::fmt;
+use std::error;
 use crate::geo;
 
-#[derive(Clone)]
-pub struct Matrix {
-    dim : usize,
-    matrix: Vec<f64>
+// 4x4 transform, stored inline so it can be copied around freely
+#[derive(Debug,Copy,Clone)]
+pub struct Matrix4 {
+    m: [[geo::Float;4];4]
 }
 
+pub type Matrix = Matrix4;
+
+// matrices whose determinant is this small a fraction of the largest it could
+// be for the lengths of their columns are treated as singular, any smaller and
+// the inverse is dominated by rounding error
+#[cfg(not(feature = "f32"))]
+const SINGULAR_EPSILON:geo::Float = 1e-12;
+#[cfg(feature = "f32")]
+const SINGULAR_EPSILON:geo::Float = 1e-6;
 
-impl Matrix {
+const IDENTITY:[[geo::Float;4];4] = [[1.0, 0.0, 0.0, 0.0],
+                              [0.0, 1.0, 0.0, 0.0],
+                              [0.0, 0.0, 1.0, 0.0],
+                              [0.0, 0.0, 0.0, 1.0]];
 
-    pub fn with_dim( dim:usize) -> Matrix {
+impl Matrix4 {
 
-        let matrix = vec!(0.0f64; dim*dim);
-        Matrix {dim, matrix}
+    pub fn new(m:[[geo::Float;4];4]) -> Matrix4 {
+        Matrix4 {m}
     }
 
-    pub fn with_vec( matrix:Vec<f64>) -> Matrix {
-        
-        let dim = match matrix.len() {
-            16 => 4,
-            9  => 3,
-            4  => 2,
-            _  => 0,
-        };
+    pub fn with_vec(matrix:Vec<geo::Float>) -> Matrix4 {
+
+        assert!(matrix.len() == 16);
+
+        let mut m = [[0.0;4];4];
+
+        for (i, v) in matrix.into_iter().enumerate() {
+            m[i/4][i%4] = v;
+        }
 
-        Matrix {dim, matrix}
+        Matrix4 {m}
     }
 
-    pub fn transpose(&self) -> Matrix {
+    pub fn transpose(&self) -> Matrix4 {
         
-        let mut ret = Matrix::with_dim(4);
+        let mut ret = Matrix4 {m:[[0.0;4];4]};
 
         for row in 0..4 {
             for col in 0..4 {
 
-                ret.set(col,row,self.get(row,col));
+                ret.m[col][row] = self.m[row][col];
             }
         }
 
         ret
     }
 
-    pub fn identity() -> Matrix {
-
-        let dim = 4usize;
-        let matrix = vec![1.0, 0.0, 0.0, 0.0,
-                         0.0, 1.0, 0.0, 0.0,
-                         0.0, 0.0, 1.0, 0.0,
-                         0.0, 0.0, 0.0, 1.0];
- 
-        Matrix { dim, matrix}
+    pub fn identity() -> Matrix4 {
+        Matrix4 {m:IDENTITY}
+    }
 
+    pub fn get(&self, row:usize, col:usize) -> geo::Float {
+        self.m[row][col]
     }
 
-    pub fn get( &self,row:usize, col:usize) -> f64 {
-        
-        self.matrix[row*self.dim + col]
+    pub fn set(&mut self, row:usize, col:usize, val:geo::Float) {
+        self.m[row][col] = val;
     }
 
-    pub fn set(&mut self,row:usize, col:usize, val:f64) {
+    // 2x2 determinants of the top two rows (s) and bottom two rows (c),
+    // shared by det() and inverse() so neither needs any submatrices
+    fn sub_dets(&self) -> ([geo::Float;6],[geo::Float;6]) {
+
+        let a = &self.m;
+
+        let s = [a[0][0]*a[1][1] - a[1][0]*a[0][1],
+                 a[0][0]*a[1][2] - a[1][0]*a[0][2],
+                 a[0][0]*a[1][3] - a[1][0]*a[0][3],
+                 a[0][1]*a[1][2] - a[1][1]*a[0][2],
+                 a[0][1]*a[1][3] - a[1][1]*a[0][3],
+                 a[0][2]*a[1][3] - a[1][2]*a[0][3]];
 
-        self.matrix[row*self.dim + col] = val;
+        let c = [a[2][0]*a[3][1] - a[3][0]*a[2][1],
+                 a[2][0]*a[3][2] - a[3][0]*a[2][2],
+                 a[2][0]*a[3][3] - a[3][0]*a[2][3],
+                 a[2][1]*a[3][2] - a[3][1]*a[2][2],
+                 a[2][1]*a[3][3] - a[3][1]*a[2][3],
+                 a[2][2]*a[3][3] - a[3][2]*a[2][3]];
 
+        (s, c)
     }
 
-    pub fn det(&self) -> f64 {
+    pub fn det(&self) -> geo::Float {
 
-        if self.dim == 2 { 
-            return self.get(0,0)*self.get(1,1) - self.get(0,1)*self.get(1,0);
-        }
+        let (s, c) = self.sub_dets();
 
-        let mut ret = 0.0;
+        s[0]*c[5] - s[1]*c[4] + s[2]*c[3] + s[3]*c[2] - s[4]*c[1] + s[5]*c[0]
+    }
 
-        for col in 0..self.dim {
+    pub fn is_invertible(&self) -> bool {
+        !self.is_singular(self.det())
+    }
 
-            ret += self.get(0,col)*self.cofactor(0,col);
-        }
+    // Compares det with the product of the lengths of the upper 3x3's columns,
+    // the most it can be for an affine transform, so a tiny uniform scale is
+    // as invertible as a large one and only columns close to lining up count.
+    fn is_singular(&self, det:geo::Float) -> bool {
 
-        return ret;
+        let bound:geo::Float = (0..3)
+            .map(|col| (0..3).map(|row| self.m[row][col]*self.m[row][col]).sum::<geo::Float>().sqrt())
+            .product();
 
+        det.abs() <= SINGULAR_EPSILON*bound
     }
 
-    pub fn sub(&self, i:usize,j:usize) -> Matrix {
+    // panics on a singular matrix, use try_inverse for anything user supplied
+    pub fn inverse(&self) -> Matrix4 {
+        self.try_inverse().expect("matrix is not invertible")
+    }
 
-        let mut vec = Vec::new();
+    // closed form adjugate over determinant
+    pub fn try_inverse(&self) -> Option<Matrix4> {
 
-        for row in 0..self.dim {
-            for col in 0..self.dim {
-                if row != i && col != j {
-                        
-                    vec.push(self.get(row,col));
-                }
-            }
-        }
+        let a = &self.m;
+        let (s, c) = self.sub_dets();
 
-        Matrix::with_vec(vec)
+        let det = s[0]*c[5] - s[1]*c[4] + s[2]*c[3] + s[3]*c[2] - s[4]*c[1] + s[5]*c[0];
 
-    }
+        if self.is_singular(det) {
+            return None;
+        }
 
-    pub fn minor(&self, i:usize, j:usize) -> f64 {
+        let adj = [[ a[1][1]*c[5] - a[1][2]*c[4] + a[1][3]*c[3],
+                    -a[0][1]*c[5] + a[0][2]*c[4] - a[0][3]*c[3],
+                     a[3][1]*s[5] - a[3][2]*s[4] + a[3][3]*s[3],
+                    -a[2][1]*s[5] + a[2][2]*s[4] - a[2][3]*s[3]],
 
-        self.sub(i,j).det()
+                   [-a[1][0]*c[5] + a[1][2]*c[2] - a[1][3]*c[1],
+                     a[0][0]*c[5] - a[0][2]*c[2] + a[0][3]*c[1],
+                    -a[3][0]*s[5] + a[3][2]*s[2] - a[3][3]*s[1],
+                     a[2][0]*s[5] - a[2][2]*s[2] + a[2][3]*s[1]],
 
-    }
+                   [ a[1][0]*c[4] - a[1][1]*c[2] + a[1][3]*c[0],
+                    -a[0][0]*c[4] + a[0][1]*c[2] - a[0][3]*c[0],
+                     a[3][0]*s[4] - a[3][1]*s[2] + a[3][3]*s[0],
+                    -a[2][0]*s[4] + a[2][1]*s[2] - a[2][3]*s[0]],
 
-    pub fn cofactor(&self, i:usize, j:usize) -> f64 {
+                   [-a[1][0]*c[3] + a[1][1]*c[1] - a[1][2]*c[0],
+                     a[0][0]*c[3] - a[0][1]*c[1] + a[0][2]*c[0],
+                    -a[3][0]*s[3] + a[3][1]*s[1] - a[3][2]*s[0],
+                     a[2][0]*s[3] - a[2][1]*s[1] + a[2][2]*s[0]]];
 
-        if (i + j) % 2 == 0 {
-            return self.minor(i,j);
-        } else {
-            return self.minor(i,j)*-1.0;
+        let mut ret = Matrix4 {m:adj};
+
+        for row in ret.m.iter_mut() {
+            for v in row.iter_mut() {
+                *v /= det;
+            }
         }
 
+        Some(ret)
     }
 
-    pub fn inverse(&self) -> Matrix {
+    pub fn translate(x:geo::Float,y:geo::Float,z:geo::Float) -> Matrix4 {
 
-        let det = self.det();
-        let mut ret = Matrix::with_dim(self.dim);
+        Matrix4 {m:[[1.0, 0.0, 0.0, x],
+                    [0.0, 1.0, 0.0, y],
+                    [0.0, 0.0, 1.0, z],
+                    [0.0, 0.0, 0.0, 1.0]]}
+    }
 
-        assert!(det != 0.0);
-        
-        for row in 0..self.dim {
-            for col in 0..self.dim {
-                let c   = self.cofactor(row,col);
-                let val = c/det;
-                ret.set(col,row,val);
-            }
-        }
+    pub fn scale(x:geo::Float,y:geo::Float,z:geo::Float) -> Matrix4 {
 
-        ret
+        Matrix4 {m:[[  x, 0.0, 0.0, 0.0],
+                    [0.0,   y, 0.0, 0.0],
+                    [0.0, 0.0,   z, 0.0],
+                    [0.0, 0.0, 0.0, 1.0]]}
     }
 
-    pub fn translate(x:f64,y:f64,z:f64) -> Matrix {
+    pub fn rotate_x(r:geo::Float) -> Matrix4 {
 
-        let vec = vec![1.0, 0.0, 0.0, x,
-                       0.0, 1.0, 0.0, y,
-                       0.0, 0.0, 1.0, z,
-                       0.0, 0.0, 0.0, 1.0];
+        Matrix4 {m:[[1.0, 0.0,     0.0,      0.0],
+                    [0.0, r.cos(),-r.sin(),  0.0],
+                    [0.0, r.sin(), r.cos(),  0.0],
+                    [0.0, 0.0,     0.0,      1.0]]}
+    }
 
-        Matrix { dim:4, matrix:vec}
+    pub fn rotate_y(r:geo::Float) -> Matrix4 {
 
+        Matrix4 {m:[[ r.cos(), 0.0, r.sin(), 0.0],
+                    [ 0.0,     1.0, 0.0,     0.0],
+                    [-r.sin(), 0.0, r.cos(), 0.0],
+                    [ 0.0,     0.0, 0.0,     1.0]]}
     }
 
-    pub fn scale(x:f64,y:f64,z:f64) -> Matrix {
+    pub fn rotate_z(r:geo::Float) -> Matrix4 {
 
-        let vec = vec![  x, 0.0, 0.0, 0.0,
-                       0.0,   y, 0.0, 0.0,
-                       0.0, 0.0,   z, 0.0,
-                       0.0, 0.0, 0.0, 1.0];
+        Matrix4 {m:[[r.cos(),-r.sin(), 0.0, 0.0],
+                    [r.sin(), r.cos(), 0.0, 0.0],
+                    [0.0,     0.0,     1.0, 0.0],
+                    [0.0,     0.0,     0.0, 1.0]]}
+    }
 
-        Matrix { dim:4, matrix:vec}
+    pub fn shear(xy:geo::Float,xz:geo::Float,yx:geo::Float,yz:geo::Float,zx:geo::Float,zy:geo::Float) -> Matrix4 {
 
+        Matrix4 {m:[[1.0,  xy,  xz, 0.0],
+                    [ yx, 1.0,  yz, 0.0],
+                    [ zx,  zy, 1.0, 0.0],
+                    [0.0, 0.0, 0.0, 1.0]]}
     }
 
-    pub fn rotate_x(r:f64) -> Matrix {
+    // right handed rotation of r radians about an arbitrary axis
+    pub fn rotate(axis:geo::Vec3, r:geo::Float) -> Matrix4 {
 
-        let vec = vec![1.0, 0.0,     0.0,      0.0,
-                       0.0, r.cos(),-r.sin(),  0.0,
-                       0.0, r.sin(), r.cos(),  0.0,
-                       0.0, 0.0,     0.0,      1.0];
+        let a = axis.norm();
+        let (x, y, z) = (a.x, a.y, a.z);
+        let (s, c)    = r.sin_cos();
+        let t         = 1.0 - c;
 
-        Matrix { dim:4, matrix:vec}
+        Matrix4 {m:[[t*x*x + c,   t*x*y - s*z, t*x*z + s*y, 0.0],
+                    [t*x*y + s*z, t*y*y + c,   t*y*z - s*x, 0.0],
+                    [t*x*z - s*y, t*y*z + s*x, t*z*z + c,   0.0],
+                    [0.0,         0.0,         0.0,         1.0]]}
     }
 
-    pub fn rotate_y(r:f64) -> Matrix {
+    // view transform for an eye at `from` looking towards `to`, moves the
+    // world so the eye sits at the origin looking down -z
+    pub fn look_at(from:geo::Point3, to:geo::Point3, up:geo::Vec3) -> Matrix4 {
 
-        let vec = vec![r.cos(), 0.0, r.sin(), 0.0,
-                       0.0,     1.0, 0.0,     0.0,
-                      -r.sin(), 0.0, r.cos(), 0.0,
-                       0.0,     0.0, 0.0,     1.0];
+        let forward = (to - from).norm();
+        let left    = forward.cross(up.norm());
+        let true_up = left.cross(forward);
 
-        Matrix { dim:4, matrix:vec}
+        let orientation = Matrix4 {m:[[ left.x,     left.y,     left.z,    0.0],
+                                      [ true_up.x,  true_up.y,  true_up.z, 0.0],
+                                      [-forward.x, -forward.y, -forward.z, 0.0],
+                                      [ 0.0,        0.0,        0.0,       1.0]]};
+
+        orientation * Matrix4::translate(-from.x, -from.y, -from.z)
     }
 
-    pub fn rotate_z(r:f64) -> Matrix {
+    // The chainable forms apply each step after everything before it, so
+    // identity().then_rotate_x(a).then_scale(..).then_translate(..) reads in
+    // the order the operations happen rather than the reverse order of the
+    // product. The constructors of the same name without then_ build a single
+    // transform.
 
-        let vec = vec![r.cos(),-r.sin(), 0.0, 0.0,
-                       r.sin(), r.cos(), 0.0, 0.0,
-                       0.0,     0.0,     1.0, 0.0,
-                       0.0,     0.0,     0.0, 1.0];
+    pub fn then_translate(self, x:geo::Float, y:geo::Float, z:geo::Float) -> Matrix4 {
+        Matrix4::translate(x, y, z) * self
+    }
 
-        Matrix { dim:4, matrix:vec}
+    pub fn then_scale(self, x:geo::Float, y:geo::Float, z:geo::Float) -> Matrix4 {
+        Matrix4::scale(x, y, z) * self
     }
 
-    pub fn shear(xy:f64,xz:f64,yx:f64,yz:f64,zx:f64,zy:f64) -> Matrix {
+    pub fn then_rotate_x(self, r:geo::Float) -> Matrix4 {
+        Matrix4::rotate_x(r) * self
+    }
 
-        let vec = vec![1.0,  xy,  xz, 0.0,
-                        yx, 1.0,  yz, 0.0,
-                        zx,  zy, 1.0, 0.0,
-                       0.0, 0.0, 0.0, 1.0];
+    pub fn then_rotate_y(self, r:geo::Float) -> Matrix4 {
+        Matrix4::rotate_y(r) * self
+    }
+
+    pub fn then_rotate_z(self, r:geo::Float) -> Matrix4 {
+        Matrix4::rotate_z(r) * self
+    }
 
-        Matrix { dim:4, matrix:vec}
+    pub fn then_rotate(self, axis:geo::Vec3, r:geo::Float) -> Matrix4 {
+        Matrix4::rotate(axis, r) * self
+    }
+
+    pub fn then_shear(self, xy:geo::Float, xz:geo::Float, yx:geo::Float, yz:geo::Float, zx:geo::Float, zy:geo::Float) -> Matrix4 {
+        Matrix4::shear(xy, xz, yx, yz, zx, zy) * self
     }
 }
                       
-impl ops::Mul for Matrix {
-    type Output = Matrix;
+impl ops::Mul for Matrix4 {
+    type Output = Matrix4;
 
-    fn mul(self,rhs:Matrix) -> Matrix {
+    fn mul(self,rhs:Matrix4) -> Matrix4 {
 
-        let mut ret = Matrix::with_dim(4);
+        let mut ret = Matrix4 {m:[[0.0;4];4]};
 
         for row in 0..4 {
             for col in 0..4 {
                     
-                let prod = self.get(row,0) * rhs.get(0,col) +
-                           self.get(row,1) * rhs.get(1,col) +
-                           self.get(row,2) * rhs.get(2,col) + 
-                           self.get(row,3) * rhs.get(3,col);
-
-       		ret.set(row,col,prod);
-	     }
-	}
+                ret.m[row][col] = self.m[row][0] * rhs.m[0][col] +
+                                  self.m[row][1] * rhs.m[1][col] +
+                                  self.m[row][2] * rhs.m[2][col] +
+                                  self.m[row][3] * rhs.m[3][col];
+            }
+        }
 
-        return ret;
+        ret
     }
 }
 
-impl ops::Mul<geo::Geo> for Matrix {
-    type Output = geo::Geo;
+// points carry an implicit w of 1 so they pick up translation
+impl ops::Mul<geo::Point3> for Matrix4 {
+    type Output = geo::Point3;
 
-    fn mul(self,rhs:geo::Geo) -> geo::Geo {
+    fn mul(self,rhs:geo::Point3) -> geo::Point3 {
 
-        let mut ret = geo::Geo::new(0.0,0.0,0.0,0.0);
+        let m = &self.m;
 
-        ret.x = self.get(0,0)*rhs.x + self.get(0,1)*rhs.y + self.get(0,2)*rhs.z  + self.get(0,3)*rhs.w;
-        ret.y = self.get(1,0)*rhs.x + self.get(1,1)*rhs.y + self.get(1,2)*rhs.z  + self.get(1,3)*rhs.w;
-        ret.z = self.get(2,0)*rhs.x + self.get(2,1)*rhs.y + self.get(2,2)*rhs.z  + self.get(2,3)*rhs.w;
-        ret.w = self.get(3,0)*rhs.x + self.get(3,1)*rhs.y + self.get(3,2)*rhs.z  + self.get(3,3)*rhs.w;
-        
-        ret
+        let p = geo::Point3::new(m[0][0]*rhs.x + m[0][1]*rhs.y + m[0][2]*rhs.z + m[0][3],
+                                 m[1][0]*rhs.x + m[1][1]*rhs.y + m[1][2]*rhs.z + m[1][3],
+                                 m[2][0]*rhs.x + m[2][1]*rhs.y + m[2][2]*rhs.z + m[2][3]);
+
+        let w = m[3][0]*rhs.x + m[3][1]*rhs.y + m[3][2]*rhs.z + m[3][3];
+
+        if w == 1.0 {
+            return p;
+        }
+
+        geo::Point3::new(p.x/w, p.y/w, p.z/w)
     }
 }
 
-impl fmt::Debug for Matrix {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Matrix")
-         .field("dim", &self.dim)
-         .field("matrix", &self.matrix.iter().fold(String::new(), |acc, &arg| acc + "   " + &arg.to_string()))
-         .finish()
+// vectors carry an implicit w of 0 so translation never applies to them
+impl ops::Mul<geo::Vec3> for Matrix4 {
+    type Output = geo::Vec3;
+
+    fn mul(self,rhs:geo::Vec3) -> geo::Vec3 {
+
+        let m = &self.m;
+
+        geo::Vec3::new(m[0][0]*rhs.x + m[0][1]*rhs.y + m[0][2]*rhs.z,
+                       m[1][0]*rhs.x + m[1][1]*rhs.y + m[1][2]*rhs.z,
+                       m[2][0]*rhs.x + m[2][1]*rhs.y + m[2][2]*rhs.z)
     }
 }
 
-impl PartialEq for Matrix {
+impl PartialEq for Matrix4 {
     fn eq(&self, rhs: &Self) -> bool {
     
-        self.dim == rhs.dim && 
-        self.matrix.iter().zip(&rhs.matrix).all(|(a,b)| (a-b).abs() < geo::EPSILON)
+        self.m.iter().flatten()
+            .zip(rhs.m.iter().flatten())
+            .all(|(a,b)| (a-b).abs() < geo::EPSILON)
     
     }
 }
 
-impl Eq for Matrix {}
+impl Eq for Matrix4 {}
+
+// A transform bundled with the inverses every ray and normal needs. The
+// fields are private so the inverses are only ever computed here and can
+// never disagree with the matrix they were derived from.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+    inverse_transpose: Matrix4,
+}
+
+impl Transform {
+
+    // None if the matrix cannot be inverted
+    pub fn new(m:Matrix4) -> Option<Transform> {
+
+        let inverse = m.try_inverse()?;
+
+        Some(Transform {matrix:m, inverse, inverse_transpose:inverse.transpose()})
+    }
+
+    pub fn identity() -> Transform {
+        Transform {matrix:Matrix4::identity(),
+                   inverse:Matrix4::identity(),
+                   inverse_transpose:Matrix4::identity()}
+    }
+
+    pub fn matrix(&self) -> Matrix4 {
+        self.matrix
+    }
+
+    pub fn inverse(&self) -> Matrix4 {
+        self.inverse
+    }
+
+    pub fn inverse_transpose(&self) -> Matrix4 {
+        self.inverse_transpose
+    }
+
+    // self applied after inner, as one transform, with the inverse made from
+    // the two already known rather than inverted again
+    pub fn compose(&self, inner:&Transform) -> Transform {
+
+        let inverse = inner.inverse*self.inverse;
+
+        Transform {matrix:self.matrix*inner.matrix, inverse, inverse_transpose:inverse.transpose()}
+    }
+}
+
+// raised when a shape is given a transform it could never be rendered with
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct TransformError {
+    pub kind: &'static str,
+    pub id: i32,
+    pub det: geo::Float,
+}
+
+impl TransformError {
+
+    pub fn new(kind:&'static str, id:i32, matrix:Matrix4) -> TransformError {
+        TransformError {kind, id, det:matrix.det()}
+    }
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} was given a non-invertible transform (det = {})", self.kind, self.id, self.det)
+    }
+}
+
+impl error::Error for TransformError {}
+
+// Smaller matrices only exist to spell out the cofactor expansion the
+// closed form inverse is derived from, so they are confined to the tests.
+#[cfg(test)]
+#[derive(Debug,Clone,PartialEq)]
+pub struct Matrix3 {
+    m: [[geo::Float;3];3]
+}
+
+#[cfg(test)]
+#[derive(Debug,Clone,PartialEq)]
+pub struct Matrix2 {
+    m: [[geo::Float;2];2]
+}
+
+#[cfg(test)]
+impl Matrix2 {
+
+    pub fn with_vec(v:Vec<geo::Float>) -> Matrix2 {
+        Matrix2 {m:[[v[0], v[1]], [v[2], v[3]]]}
+    }
+
+    pub fn get(&self, row:usize, col:usize) -> geo::Float {
+        self.m[row][col]
+    }
+
+    pub fn det(&self) -> geo::Float {
+        self.m[0][0]*self.m[1][1] - self.m[0][1]*self.m[1][0]
+    }
+}
+
+#[cfg(test)]
+impl Matrix3 {
+
+    pub fn with_vec(v:Vec<geo::Float>) -> Matrix3 {
+        Matrix3 {m:[[v[0], v[1], v[2]], [v[3], v[4], v[5]], [v[6], v[7], v[8]]]}
+    }
+
+    pub fn get(&self, row:usize, col:usize) -> geo::Float {
+        self.m[row][col]
+    }
+
+    pub fn sub(&self, i:usize, j:usize) -> Matrix2 {
+
+        let v = (0..3).filter(|&row| row != i)
+            .flat_map(|row| (0..3).filter(move |&col| col != j).map(move |col| (row, col)))
+            .map(|(row, col)| self.m[row][col])
+            .collect();
+
+        Matrix2::with_vec(v)
+    }
+
+    pub fn minor(&self, i:usize, j:usize) -> geo::Float {
+        self.sub(i,j).det()
+    }
+
+    // is_multiple_of would need rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn cofactor(&self, i:usize, j:usize) -> geo::Float {
+
+        if (i + j) % 2 == 0 {
+            self.minor(i,j)
+        } else {
+            -self.minor(i,j)
+        }
+    }
+
+    pub fn det(&self) -> geo::Float {
+        (0..3).map(|col| self.m[0][col]*self.cofactor(0,col)).sum()
+    }
+}
+
+#[cfg(test)]
+impl Matrix4 {
+
+    pub fn sub(&self, i:usize, j:usize) -> Matrix3 {
+
+        let v = (0..4).filter(|&row| row != i)
+            .flat_map(|row| (0..4).filter(move |&col| col != j).map(move |col| (row, col)))
+            .map(|(row, col)| self.m[row][col])
+            .collect();
+
+        Matrix3::with_vec(v)
+    }
+
+    pub fn minor(&self, i:usize, j:usize) -> geo::Float {
+        self.sub(i,j).det()
+    }
+
+    // is_multiple_of would need rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn cofactor(&self, i:usize, j:usize) -> geo::Float {
+
+        if (i + j) % 2 == 0 {
+            self.minor(i,j)
+        } else {
+            -self.minor(i,j)
+        }
+    }
+}
 
 
 #[cfg(test)]
@@ -282,7 +543,7 @@ mod tests {
     #[test]
     fn matrix_3x3 () {
 
-        let m = Matrix::with_vec(vec![-3.0, 5.0, 0.0,
+        let m = Matrix3::with_vec(vec![-3.0, 5.0, 0.0,
                                        1.0,-2.0,-7.0,
                                        0.0, 1.0, 1.0]);
 
@@ -294,7 +555,7 @@ mod tests {
     #[test]
     fn matrix_2x2 () {
 
-        let m = Matrix::with_vec(vec![-3.0, 5.0,
+        let m = Matrix2::with_vec(vec![-3.0, 5.0,
                                        1.0,-2.0]);
 
         assert_eq!(m.get(0,0),-3.0);
@@ -357,9 +618,11 @@ mod tests {
                      8.0, 6.0, 4.0, 1.0,
                      0.0, 0.0, 0.0, 1.0]);
 
-        let v = geo::Geo::new(1.0,2.0,3.0,1.0);
+        let p = geo::Point3::new(1.0,2.0,3.0);
+        let v = geo::Vec3::new(1.0,2.0,3.0);
 
-        assert_eq!(a*v, geo::Geo::new(18.0,24.0,33.0,1.0));
+        assert_eq!(a*p, geo::Point3::new(18.0,24.0,33.0));
+        assert_eq!(a*v, geo::Vec3::new(14.0,22.0,32.0));
 
     }
 
@@ -375,15 +638,15 @@ mod tests {
 
         let i = Matrix::identity();
 
-        let r1 = a.clone()*i;
+        let r1 = a*i;
 
         assert_eq!(r1, a);
 
-        let b = geo::Geo::new(1.0,2.0,3.0,4.0);
-        
-        let r2 = Matrix::identity() * b;
+        let p = geo::Point3::new(1.0,2.0,3.0);
+        let v = geo::Vec3::new(1.0,2.0,3.0);
 
-        assert_eq!(r2,b);
+        assert_eq!(Matrix::identity() * p, p);
+        assert_eq!(Matrix::identity() * v, v);
     }
 
     #[test]
@@ -411,7 +674,7 @@ mod tests {
     #[test]
     fn det () {
     
-        let a = Matrix::with_vec(
+        let a = Matrix2::with_vec(
                 vec![1.0,5.0,-3.0,2.0]);
 
 
@@ -422,12 +685,12 @@ mod tests {
     #[test]
     fn submatrix () {
 
-        let a = Matrix::with_vec(
+        let a = Matrix3::with_vec(
                 vec![ 1.0, 5.0, 0.0,
                      -3.0, 2.0, 7.0,
                       0.0, 6.0, -3.0]);
         
-        let e1= Matrix::with_vec(
+        let e1= Matrix2::with_vec(
                 vec![-3.0, 2.0,
                       0.0, 6.0]);
 
@@ -440,7 +703,7 @@ mod tests {
                      -1.0, 0.0, 8.0, 2.0,
                      -7.0, 1.0,-1.0, 1.0]);
 
-        let e2= Matrix::with_vec(
+        let e2= Matrix3::with_vec(
                 vec![-6.0, 1.0, 6.0,
                      -8.0, 8.0, 6.0,
                      -7.0,-1.0, 1.0]);
@@ -451,7 +714,7 @@ mod tests {
     #[test]
     fn minor () {
 
-        let a = Matrix::with_vec(
+        let a = Matrix3::with_vec(
                 vec![ 3.0, 5.0, 0.0,
                       2.0,-1.0,-7.0,
                       6.0,-1.0, 5.0]);
@@ -462,7 +725,7 @@ mod tests {
     #[test]
     fn cofactor () {
 
-        let a = Matrix::with_vec(
+        let a = Matrix3::with_vec(
                 vec![ 3.0, 5.0, 0.0,
                       2.0,-1.0,-7.0,
                       6.0,-1.0, 5.0]);
@@ -476,7 +739,7 @@ mod tests {
     #[test]
     fn determinant_3x3 () {
 
-        let a = Matrix::with_vec(
+        let a = Matrix3::with_vec(
                 vec![ 1.0, 2.0, 6.0,
                      -5.0, 8.0,-4.0,
                       2.0, 6.0, 4.0]);
@@ -525,6 +788,34 @@ mod tests {
     }
 
 
+    #[test]
+    fn try_inverse () {
+
+        let singular = Matrix::scale(0.0, 1.0, 1.0);
+        // the x and y axes end up almost on top of each other
+        let nearly   = Matrix::shear(1.0, 0.0, 1.0 - 1e-13, 0.0, 0.0, 0.0);
+
+        assert!(!singular.is_invertible());
+        assert_eq!(singular.try_inverse(), None);
+        assert_eq!(nearly.try_inverse(), None);
+        assert!(Transform::new(singular).is_none());
+
+        let a = Matrix::scale(0.01, 0.01, 0.01);
+        assert_eq!(a.try_inverse(), Some(Matrix::scale(100.0, 100.0, 100.0)));
+
+        // however small a uniform scale, and whatever the other axes do
+        let tiny = Matrix::scale(1e-4, 1e-4, 1e-4);
+        assert!(tiny.is_invertible());
+        assert_eq!(tiny.inverse()*tiny, Matrix::identity());
+        assert!(Matrix::scale(1.0, 1e-5, 1e-8).is_invertible());
+    }
+
+    #[test]
+    #[should_panic]
+    fn inverse_singular () {
+        Matrix::scale(1.0, 0.0, 1.0).inverse();
+    }
+
     #[test]
     fn inverse0 () {
 
@@ -591,9 +882,9 @@ mod tests {
     fn translate0 () {
 
         let t = Matrix::translate(5.0, -3.0, 2.0);
-        let p = geo::Geo::point(-3.0, 4.0, 5.0);
+        let p = geo::Point3::new(-3.0, 4.0, 5.0);
 
-        assert_eq!(t*p, geo::Geo::point(2.0,1.0,7.0));
+        assert_eq!(t*p, geo::Point3::new(2.0,1.0,7.0));
 
     }
 
@@ -602,9 +893,9 @@ mod tests {
 
         let t0 = Matrix::translate(5.0, -3.0, 2.0);
         let t1 = t0.inverse();
-        let p = geo::Geo::point(-3.0, 4.0, 5.0);
+        let p = geo::Point3::new(-3.0, 4.0, 5.0);
 
-        assert_eq!(t1*p, geo::Geo::point(-8.0,7.0,3.0));
+        assert_eq!(t1*p, geo::Point3::new(-8.0,7.0,3.0));
 
     }
 
@@ -612,9 +903,9 @@ mod tests {
     fn translate2 () {
 
         let t = Matrix::translate(5.0, -3.0, 2.0);
-        let v = geo::Geo::vector(-3.0, 4.0, 5.0);
+        let v = geo::Vec3::new(-3.0, 4.0, 5.0);
 
-        assert_eq!(t*v, geo::Geo::vector(-3.0,4.0,5.0));
+        assert_eq!(t*v, geo::Vec3::new(-3.0,4.0,5.0));
 
     }
 
@@ -623,9 +914,9 @@ mod tests {
     fn scale0 () {
 
         let t = Matrix::scale(2.0, 3.0, 4.0);
-        let p = geo::Geo::point(-4.0, 6.0, 8.0);
+        let p = geo::Point3::new(-4.0, 6.0, 8.0);
 
-        assert_eq!(t*p, geo::Geo::point(-8.0,18.0,32.0));
+        assert_eq!(t*p, geo::Point3::new(-8.0,18.0,32.0));
 
     }
 
@@ -633,9 +924,9 @@ mod tests {
     fn scale1 () {
 
         let t = Matrix::scale(2.0, 3.0, 4.0);
-        let p = geo::Geo::vector(-4.0, 6.0, 8.0);
+        let p = geo::Vec3::new(-4.0, 6.0, 8.0);
 
-        assert_eq!(t*p, geo::Geo::vector(-8.0,18.0,32.0));
+        assert_eq!(t*p, geo::Vec3::new(-8.0,18.0,32.0));
 
     }
 
@@ -643,9 +934,9 @@ mod tests {
     fn scale2 () {
 
         let t = Matrix::scale(2.0, 3.0, 4.0).inverse(); 
-        let p  = geo::Geo::vector(-4.0, 6.0, 8.0);
+        let p  = geo::Vec3::new(-4.0, 6.0, 8.0);
 
-        assert_eq!(t*p, geo::Geo::vector(-2.0,2.0,2.0));
+        assert_eq!(t*p, geo::Vec3::new(-2.0,2.0,2.0));
 
     }
 
@@ -653,9 +944,9 @@ mod tests {
     fn reflect () {
 
         let t = Matrix::scale(-1.0, 1.0, 1.0); 
-        let p  = geo::Geo::point(2.0, 3.0, 4.0);
+        let p  = geo::Point3::new(2.0, 3.0, 4.0);
 
-        assert_eq!(t*p, geo::Geo::point(-2.0,3.0,4.0));
+        assert_eq!(t*p, geo::Point3::new(-2.0,3.0,4.0));
 
     }
 
@@ -666,10 +957,10 @@ mod tests {
         let t1 = Matrix::rotate_x(PI/4.0);
         let t2 = Matrix::rotate_x(PI/2.0);
 
-        let p  = geo::Geo::point(0.0, 1.0, 0.0);
+        let p  = geo::Point3::new(0.0, 1.0, 0.0);
 
-        assert_eq!(t1*p, geo::Geo::point(0.0, 2.0_f64.sqrt()/2.0, 2.0_f64.sqrt()/2.0));
-        assert_eq!(t2*p, geo::Geo::point(0.0, 0.0, 1.0));
+        assert_eq!(t1*p, geo::Point3::new(0.0, geo::Float::sqrt(2.0)/2.0, geo::Float::sqrt(2.0)/2.0));
+        assert_eq!(t2*p, geo::Point3::new(0.0, 0.0, 1.0));
 
     }   
 
@@ -679,10 +970,10 @@ mod tests {
         let t1 = Matrix::rotate_y(PI/4.0);
         let t2 = Matrix::rotate_y(PI/2.0);
 
-        let p  = geo::Geo::point(0.0, 0.0, 1.0);
+        let p  = geo::Point3::new(0.0, 0.0, 1.0);
 
-        assert_eq!(t1*p, geo::Geo::point(2.0_f64.sqrt()/2.0, 0.0 ,2.0_f64.sqrt()/2.0));
-        assert_eq!(t2*p, geo::Geo::point(1.0, 0.0, 0.0));
+        assert_eq!(t1*p, geo::Point3::new(geo::Float::sqrt(2.0)/2.0, 0.0 ,geo::Float::sqrt(2.0)/2.0));
+        assert_eq!(t2*p, geo::Point3::new(1.0, 0.0, 0.0));
 
     }   
 
@@ -693,57 +984,156 @@ mod tests {
         let t1 = Matrix::rotate_z(PI/4.0);
         let t2 = Matrix::rotate_z(PI/2.0);
 
-        let p  = geo::Geo::point(0.0, 1.0, 0.0);
+        let p  = geo::Point3::new(0.0, 1.0, 0.0);
 
-        assert_eq!(t1*p, geo::Geo::point(-2.0_f64.sqrt()/2.0,2.0_f64.sqrt()/2.0, 0.0));
-        assert_eq!(t2*p, geo::Geo::point(-1.0, 0.0, 0.0));
+        assert_eq!(t1*p, geo::Point3::new(-geo::Float::sqrt(2.0)/2.0,geo::Float::sqrt(2.0)/2.0, 0.0));
+        assert_eq!(t2*p, geo::Point3::new(-1.0, 0.0, 0.0));
 
     }   
 
     #[test]
     fn shear () {
 
-        let p = geo::Geo::point(2.0,3.0,4.0);
+        let p = geo::Point3::new(2.0,3.0,4.0);
 
-        assert_eq!(Matrix::shear(1.0,0.0,0.0,0.0,0.0,0.0)*p, geo::Geo::point(5.0,3.0,4.0));
-        assert_eq!(Matrix::shear(0.0,1.0,0.0,0.0,0.0,0.0)*p, geo::Geo::point(6.0,3.0,4.0));
-        assert_eq!(Matrix::shear(0.0,0.0,1.0,0.0,0.0,0.0)*p, geo::Geo::point(2.0,5.0,4.0));
-        assert_eq!(Matrix::shear(0.0,0.0,0.0,1.0,0.0,0.0)*p, geo::Geo::point(2.0,7.0,4.0));
-        assert_eq!(Matrix::shear(0.0,0.0,0.0,0.0,1.0,0.0)*p, geo::Geo::point(2.0,3.0,6.0));
-        assert_eq!(Matrix::shear(0.0,0.0,0.0,0.0,0.0,1.0)*p, geo::Geo::point(2.0,3.0,7.0));
+        assert_eq!(Matrix::shear(1.0,0.0,0.0,0.0,0.0,0.0)*p, geo::Point3::new(5.0,3.0,4.0));
+        assert_eq!(Matrix::shear(0.0,1.0,0.0,0.0,0.0,0.0)*p, geo::Point3::new(6.0,3.0,4.0));
+        assert_eq!(Matrix::shear(0.0,0.0,1.0,0.0,0.0,0.0)*p, geo::Point3::new(2.0,5.0,4.0));
+        assert_eq!(Matrix::shear(0.0,0.0,0.0,1.0,0.0,0.0)*p, geo::Point3::new(2.0,7.0,4.0));
+        assert_eq!(Matrix::shear(0.0,0.0,0.0,0.0,1.0,0.0)*p, geo::Point3::new(2.0,3.0,6.0));
+        assert_eq!(Matrix::shear(0.0,0.0,0.0,0.0,0.0,1.0)*p, geo::Point3::new(2.0,3.0,7.0));
+    }
+
+    #[test]
+    fn cached_inverses() {
+
+        let m = Matrix::translate(1.0, 2.0, 3.0) * Matrix::scale(2.0, 4.0, 8.0) * Matrix::rotate_x(PI/3.0);
+        let t = Transform::new(m).unwrap();
+
+        assert_eq!(t.matrix(), m);
+        assert_eq!(t.inverse(), m.inverse());
+        assert_eq!(t.inverse_transpose(), m.inverse().transpose());
+        assert_eq!(Transform::identity(), Transform::new(Matrix::identity()).unwrap());
+    }
+
+    #[test]
+    fn fluent_chain() {
+
+        let p = geo::Point3::new(1.0,0.0,1.0);
+
+        let t = Matrix::identity()
+            .then_rotate_x(PI/2.0)
+            .then_scale(5.0,5.0,5.0)
+            .then_translate(10.0,5.0,7.0);
+
+        assert_eq!(t*p, geo::Point3::new(15.0,0.0,7.0));
+        assert_eq!(t, Matrix::translate(10.0,5.0,7.0) * Matrix::scale(5.0,5.0,5.0) * Matrix::rotate_x(PI/2.0));
+
+        let s = Matrix::identity().then_shear(1.0,0.0,0.0,0.0,0.0,0.0).then_rotate_y(PI/2.0).then_rotate_z(PI/2.0);
+        assert_eq!(s, Matrix::rotate_z(PI/2.0) * Matrix::rotate_y(PI/2.0) * Matrix::shear(1.0,0.0,0.0,0.0,0.0,0.0));
+    }
+
+    #[test]
+    fn axis_rotation() {
+
+        // the principal axes match the dedicated rotations
+        assert_eq!(Matrix::rotate(geo::Vec3::new(1.0,0.0,0.0), PI/3.0), Matrix::rotate_x(PI/3.0));
+        assert_eq!(Matrix::rotate(geo::Vec3::new(0.0,2.0,0.0), PI/3.0), Matrix::rotate_y(PI/3.0));
+        assert_eq!(Matrix::rotate(geo::Vec3::new(0.0,0.0,1.0), PI/3.0), Matrix::rotate_z(PI/3.0));
+
+        // a third of a turn about the diagonal cycles the axes
+        let r = Matrix::identity().then_rotate(geo::Vec3::new(1.0,1.0,1.0), 2.0*PI/3.0);
+        assert_eq!(r*geo::Point3::new(1.0,0.0,0.0), geo::Point3::new(0.0,1.0,0.0));
+        assert_eq!(r*geo::Vec3::new(0.0,0.0,1.0), geo::Vec3::new(1.0,0.0,0.0));
+    }
+
+    #[test]
+    fn look_at_default() {
+
+        let t = Matrix::look_at(geo::Point3::new(0.0,0.0,0.0),
+                                geo::Point3::new(0.0,0.0,-1.0),
+                                geo::Vec3::new(0.0,1.0,0.0));
+
+        assert_eq!(t, Matrix::identity());
+
+        let t = Matrix::look_at(geo::Point3::new(0.0,0.0,0.0),
+                                geo::Point3::new(0.0,0.0,1.0),
+                                geo::Vec3::new(0.0,1.0,0.0));
+
+        assert_eq!(t, Matrix::scale(-1.0,1.0,-1.0));
+    }
+
+    #[test]
+    fn look_at_moves_world() {
+
+        let t = Matrix::look_at(geo::Point3::new(0.0,0.0,8.0),
+                                geo::Point3::new(0.0,0.0,0.0),
+                                geo::Vec3::new(0.0,1.0,0.0));
+
+        assert_eq!(t, Matrix::translate(0.0,0.0,-8.0));
+    }
+
+    #[test]
+    fn look_at_arbitrary() {
+
+        let t = Matrix::look_at(geo::Point3::new(1.0,3.0,2.0),
+                                geo::Point3::new(4.0,-2.0,8.0),
+                                geo::Vec3::new(1.0,1.0,0.0));
+
+        let e = Matrix::with_vec(
+                vec![-0.50709, 0.50709,  0.67612, -2.36643,
+                      0.76772, 0.60609,  0.12122, -2.82843,
+                     -0.35857, 0.59761, -0.71714,  0.00000,
+                      0.00000, 0.00000,  0.00000,  1.00000]);
+
+        assert_eq!(t, e);
     }
 
     #[test]
     fn chain1() {
 
-        let p = geo::Geo::point(1.0,0.0,1.0);
+        let p = geo::Point3::new(1.0,0.0,1.0);
 
         let a = Matrix::rotate_x(PI/2.0);
         let b = Matrix::scale(5.0,5.0,5.0);
         let c = Matrix::translate(10.0,5.0,7.0);
 
         let p2 = a*p;
-        assert_eq!(p2,geo::Geo::point(1.0,-1.0,0.0));
+        assert_eq!(p2,geo::Point3::new(1.0,-1.0,0.0));
 
         let p3 = b*p2;
-        assert_eq!(p3,geo::Geo::point(5.0,-5.0,0.0));
+        assert_eq!(p3,geo::Point3::new(5.0,-5.0,0.0));
 
         let p4 = c*p3;
-        assert_eq!(p4,geo::Geo::point(15.0,0.0,7.0));
+        assert_eq!(p4,geo::Point3::new(15.0,0.0,7.0));
     }
 
     #[test]
     fn chain2() {
 
-        let p = geo::Geo::point(1.0,0.0,1.0);
+        let p = geo::Point3::new(1.0,0.0,1.0);
 
         let a = Matrix::rotate_x(PI/2.0);
         let b = Matrix::scale(5.0,5.0,5.0);
         let c = Matrix::translate(10.0,5.0,7.0);
 
         let p5 = c*b*a*p;
-        assert_eq!(p5,geo::Geo::point(15.0,0.0,7.0));
+        assert_eq!(p5,geo::Point3::new(15.0,0.0,7.0));
+
+    }
+
+    #[test]
+    fn compose() {
+
+        let outer = Transform::new(Matrix::rotate_y(PI/2.0)).unwrap();
+        let inner = Transform::new(Matrix::scale(2.0,2.0,2.0)*Matrix::translate(5.0,0.0,0.0)).unwrap();
+
+        let t = outer.compose(&inner);
+        let p = geo::Point3::new(1.0,0.0,0.0);
 
+        assert_eq!(t.matrix()*p,outer.matrix()*(inner.matrix()*p));
+        assert_eq!(t.inverse()*(t.matrix()*p),p);
+        assert_eq!(t.inverse_transpose(),t.inverse().transpose());
     }
 
 
@@ -0,0 +1,228 @@
+use crate::geo;
+use crate::rng;
+use crate::color;
+use crate::sample;
+use geo::consts::PI;
+
+// reflectance at normal incidence of the dielectrics the metallic workflow
+// assumes, about that of glass or plastic
+const DIELECTRIC_F0:geo::Float = 0.04;
+// roughness below this gives a distribution too sharp to sample reliably
+const MIN_ALPHA:geo::Float = 0.001;
+
+// Cook-Torrance reflection with the GGX (Trowbridge-Reitz) distribution of
+// microfacet normals, Smith masking and Schlick's Fresnel, in the metallic /
+// roughness parameterisation used by most modelling tools. A metal tints its
+// reflection with base_color and has no diffuse part, a dielectric reflects
+// a little white light and scatters base_color diffusely underneath.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct Ggx {
+    pub base_color:color::Color,
+    // 0 for a dielectric, 1 for a metal
+    pub metallic:geo::Float,
+    // perceptual roughness, 0 is a mirror and 1 very rough
+    pub roughness:geo::Float,
+}
+
+impl Ggx {
+
+    pub fn new(base_color:color::Color, metallic:geo::Float, roughness:geo::Float) -> Ggx {
+        Ggx {base_color, metallic, roughness}
+    }
+
+    fn alpha(&self) -> geo::Float {
+        (self.roughness*self.roughness).max(MIN_ALPHA)
+    }
+
+    // reflectance at normal incidence
+    fn f0(&self) -> color::Color {
+        let d = DIELECTRIC_F0*(1.0 - self.metallic);
+        color::Color::new(d, d, d) + self.base_color*self.metallic
+    }
+
+    fn diffuse_color(&self) -> color::Color {
+        self.base_color*(1.0 - self.metallic)
+    }
+
+    // chance of sampling the specular lobe rather than the diffuse one
+    fn specular_weight(&self) -> geo::Float {
+        0.5 + 0.5*self.metallic
+    }
+
+    // the brdf for light arriving from wi and leaving toward wo, all unit
+    // vectors with n on the side wo is on
+    pub fn eval(&self, n:geo::Vec3, wo:geo::Vec3, wi:geo::Vec3) -> color::Color {
+
+        let (n_o, n_i) = (n.dot(wo), n.dot(wi));
+
+        if n_o <= 0.0 || n_i <= 0.0 {
+            return color::Color::new(0.0, 0.0, 0.0);
+        }
+
+        let h = (wo + wi).norm();
+        let a = self.alpha();
+        let f = schlick(self.f0(), wo.dot(h));
+
+        let specular = f*(distribution(a, n.dot(h))*masking(a, n_o)*masking(a, n_i)/(4.0*n_o*n_i));
+        let diffuse  = (color::Color::new(1.0, 1.0, 1.0) - f)*self.diffuse_color()*(1.0/PI);
+
+        specular + diffuse
+    }
+
+    // density sample returns wi with, over solid angle
+    pub fn pdf(&self, n:geo::Vec3, wo:geo::Vec3, wi:geo::Vec3) -> geo::Float {
+
+        if n.dot(wo) <= 0.0 || n.dot(wi) <= 0.0 {
+            return 0.0;
+        }
+
+        let h    = (wo + wi).norm();
+        let spec = distribution(self.alpha(), n.dot(h))*n.dot(h)/(4.0*wo.dot(h));
+        let w    = self.specular_weight();
+
+        w*spec + (1.0 - w)*sample::cosine_hemisphere_pdf(n, wi)
+    }
+
+    // Draws an incoming direction for light leaving toward wo, either wo
+    // mirrored in a microfacet normal drawn from the distribution or cosine
+    // weighted for the diffuse part, and returns it with brdf * cos / pdf.
+    // None when the mirrored direction is below the surface.
+    pub fn sample(&self, n:geo::Vec3, wo:geo::Vec3, rng:&mut rng::Pcg32) -> Option<(geo::Vec3, color::Color)> {
+
+        let wi = if rng.next_float() < self.specular_weight() {
+
+            let a = self.alpha();
+            let (u1, u2) = (rng.next_float(), rng.next_float());
+
+            // a microfacet normal with density D(h) cos(theta_h)
+            let cos2 = (1.0 - u1)/(1.0 + (a*a - 1.0)*u1);
+            let (cos, sin) = (cos2.sqrt(), (1.0 - cos2).max(0.0).sqrt());
+            let phi = 2.0*PI*u2;
+            let (t, b) = sample::basis(n);
+
+            let h = t*(sin*phi.cos()) + b*(sin*phi.sin()) + n*cos;
+            (-wo).reflect(h)
+        } else {
+            sample::cosine_hemisphere(n, rng)
+        };
+
+        let pdf = self.pdf(n, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((wi, self.eval(n, wo, wi)*(n.dot(wi)/pdf)))
+    }
+}
+
+fn distribution(a:geo::Float, n_h:geo::Float) -> geo::Float {
+
+    if n_h <= 0.0 {
+        return 0.0;
+    }
+
+    let a2 = a*a;
+    let d  = n_h*n_h*(a2 - 1.0) + 1.0;
+
+    a2/(PI*d*d)
+}
+
+// Smith's masking for one direction
+fn masking(a:geo::Float, n_v:geo::Float) -> geo::Float {
+    let a2 = a*a;
+    2.0*n_v/(n_v + (a2 + (1.0 - a2)*n_v*n_v).sqrt())
+}
+
+fn schlick(f0:color::Color, cos:geo::Float) -> color::Color {
+    let m = (1.0 - cos).clamp(0.0, 1.0).powi(5);
+    f0 + (color::Color::new(1.0, 1.0, 1.0) - f0)*m
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn up() -> geo::Vec3 {
+        geo::Vec3::new(0.0, 1.0, 0.0)
+    }
+
+    #[test]
+    fn distribution_is_normalised() {
+
+        // the projected area of the microfacets is that of the surface
+        for &a in [0.1, 0.5, 1.0].iter() {
+
+            let steps = 20000;
+            let total = (0..steps).fold(0.0, |sum, i| {
+                let theta = (i as geo::Float + 0.5)/steps as geo::Float*PI/2.0;
+                sum + distribution(a, theta.cos())*theta.cos()*theta.sin()*2.0*PI*(PI/2.0/steps as geo::Float)
+            });
+
+            assert!((total - 1.0).abs() < 0.01, "alpha {} integrates to {}", a, total);
+        }
+    }
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+
+        let gold = Ggx::new(color::Color::new(1.0, 0.78, 0.34), 1.0, 0.5);
+        let clay = Ggx::new(color::Color::new(0.8, 0.3, 0.2), 0.0, 0.5);
+
+        assert_eq!(schlick(gold.f0(), 1.0), gold.base_color);
+        assert_eq!(schlick(clay.f0(), 1.0), color::Color::new(0.04, 0.04, 0.04));
+        assert_eq!(schlick(clay.f0(), 0.0), color::Color::new(1.0, 1.0, 1.0));
+        assert_eq!(gold.diffuse_color(), color::Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn reciprocal_and_one_sided() {
+
+        let m  = Ggx::new(color::Color::new(0.7, 0.5, 0.3), 0.3, 0.4);
+        let wo = geo::Vec3::new(0.3, 0.8, 0.1).norm();
+        let wi = geo::Vec3::new(-0.5, 0.6, 0.2).norm();
+
+        assert_eq!(m.eval(up(), wo, wi), m.eval(up(), wi, wo));
+        assert_eq!(m.eval(up(), wo, -wi), color::Color::new(0.0, 0.0, 0.0));
+        assert_eq!(m.pdf(up(), wo, -wi), 0.0);
+    }
+
+    #[test]
+    fn importance_sampling_matches_eval() {
+
+        // the reflected fraction estimated with the material's own samples and
+        // with uniform hemisphere samples has to agree
+        let wo = geo::Vec3::new(0.4, 0.8, -0.2).norm();
+
+        for &(metallic, roughness) in [(0.0, 0.3), (1.0, 0.3), (0.5, 0.8), (1.0, 1.0)].iter() {
+
+            let m = Ggx::new(color::Color::new(0.9, 0.6, 0.3), metallic, roughness);
+            let mut rng = rng::Pcg32::new(11, 0);
+            let n = 100000;
+
+            let mut importance = 0.0;
+            let mut uniform    = 0.0;
+            let mut pdf_mass   = 0.0;
+
+            for _ in 0..n {
+
+                if let Some((_, w)) = m.sample(up(), wo, &mut rng) {
+                    importance += w.green/n as geo::Float;
+                }
+
+                let (u1, u2) = (rng.next_float(), rng.next_float());
+                let (r, phi) = ((1.0 - u1*u1).sqrt(), 2.0*PI*u2);
+                let wi = geo::Vec3::new(r*phi.cos(), u1, r*phi.sin());
+
+                uniform  += m.eval(up(), wo, wi).green*u1*2.0*PI/n as geo::Float;
+                pdf_mass += m.pdf(up(), wo, wi)*2.0*PI/n as geo::Float;
+            }
+
+            assert!((importance - uniform).abs() < 0.02, "{} {}: {} vs {}", metallic, roughness, importance, uniform);
+            assert!(importance <= 1.0);
+            // reflections that end up below the surface are thrown away, so
+            // the pdf can come to less than one but never more
+            assert!(pdf_mass < 1.02, "{} {}: pdf integrates to {}", metallic, roughness, pdf_mass);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::geo;
+use crate::ray;
+use crate::simd;
+use crate::shape;
+
+// A 2x2 bundle of neighbouring rays traced together. Spheres and boxes are
+// tested for all four rays at once, anything else falls back to one ray at
+// a time. The lanes are laid out row by row: top left, top right, bottom
+// left, bottom right.
+#[derive(Debug,Copy,Clone)]
+pub struct Packet {
+    rays:[ray::Ray;4],
+    lanes:simd::Ray4,
+}
+
+impl Packet {
+
+    pub fn new(rays:[ray::Ray;4]) -> Packet {
+        Packet {rays, lanes:simd::Ray4::new(&rays)}
+    }
+
+    // splits a 4x4 block of rays, given row by row, into its four 2x2 packets
+    pub fn split_4x4(rays:&[ray::Ray;16]) -> [Packet;4] {
+
+        let quad = |x:usize, y:usize| {
+            let i = y*4 + x;
+            Packet::new([rays[i], rays[i+1], rays[i+4], rays[i+5]])
+        };
+
+        [quad(0,0), quad(2,0), quad(0,2), quad(2,2)]
+    }
+
+    pub fn rays(&self) -> &[ray::Ray;4] {
+        &self.rays
+    }
+
+    pub fn lanes(&self) -> &simd::Ray4 {
+        &self.lanes
+    }
+
+    // what Shape::intersect gives for each ray
+    pub fn intersect(&self, s:&shape::Shape) -> [Vec<ray::Isect>;4] {
+
+        match s {
+            shape::Shape::Sphere(sphere) => self.lanes.intersect(sphere),
+            _ => {
+                let r = &self.rays;
+                [s.intersect(&r[0]), s.intersect(&r[1]), s.intersect(&r[2]), s.intersect(&r[3])]
+            }
+        }
+    }
+
+    // what ray::Isect::hit of Shape::intersect gives for each ray
+    pub fn hit(&self, s:&shape::Shape) -> [ray::Isect;4] {
+
+        match s {
+            shape::Shape::Sphere(sphere) => {
+
+                let ts = self.lanes.hit(sphere);
+                let lane = |i:usize| {
+                    if ts[i] == geo::Float::INFINITY {
+                        return ray::Isect::isect(0.0, -1);
+                    }
+                    ray::Isect::isect(ts[i], sphere.id())
+                };
+
+                [lane(0), lane(1), lane(2), lane(3)]
+            }
+            _ => {
+                let xs = self.intersect(s);
+                let lane = |i:usize| ray::Isect::hit(xs[i].clone());
+                [lane(0), lane(1), lane(2), lane(3)]
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group;
+    use crate::matrix;
+
+    fn grid(n:usize) -> Vec<ray::Ray> {
+
+        let origin = geo::Point3::new(0.0, 0.0,-5.0);
+
+        (0..n*n).map(|i| {
+                let x = -1.5 + 3.0*((i % n) as geo::Float)/(n as geo::Float);
+                let y =  1.5 - 3.0*((i / n) as geo::Float)/(n as geo::Float);
+                ray::Ray::new(origin, geo::Point3::new(x, y, 0.0) - origin)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sphere_packet_matches_single_rays() {
+
+        let mut s = ray::Sphere::unit();
+        s.set_transform(matrix::Matrix::scale(1.0, 0.5, 1.0)).unwrap();
+        let s = shape::Shape::from(s);
+
+        for chunk in grid(8).chunks_exact(4) {
+
+            let p  = Packet::new([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let xs = p.intersect(&s);
+            let hs = p.hit(&s);
+
+            for i in 0..4 {
+                assert_eq!(xs[i], s.intersect(&chunk[i]));
+                assert_eq!(hs[i], ray::Isect::hit(s.intersect(&chunk[i])));
+            }
+        }
+    }
+
+    #[test]
+    fn group_packet_falls_back_to_single_rays() {
+
+        let mut g = group::Group::new();
+        g.add_child(ray::Sphere::unit());
+        g.set_transform(matrix::Matrix::translate(0.5, 0.0, 0.0)).unwrap();
+        let g = shape::Shape::from(g);
+
+        for chunk in grid(8).chunks_exact(4) {
+
+            let p  = Packet::new([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let hs = p.hit(&g);
+
+            for i in 0..4 {
+                assert_eq!(hs[i].id, ray::Isect::hit(g.intersect(&chunk[i])).id);
+            }
+        }
+    }
+
+    #[test]
+    fn split_4x4_keeps_neighbours_together() {
+
+        let rays  = grid(4);
+        let block = [rays[0], rays[1], rays[2],  rays[3],  rays[4],  rays[5],  rays[6],  rays[7],
+                     rays[8], rays[9], rays[10], rays[11], rays[12], rays[13], rays[14], rays[15]];
+
+        let packets = Packet::split_4x4(&block);
+
+        assert_eq!(packets[0].rays()[3].dir, rays[5].dir);
+        assert_eq!(packets[1].rays()[0].dir, rays[2].dir);
+        assert_eq!(packets[2].rays()[1].dir, rays[9].dir);
+        assert_eq!(packets[3].rays()[3].dir, rays[15].dir);
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::time::{Duration,Instant};
+
+// Told about a render as it runs. tile_done is called from the worker threads
+// each time a tile is finished, with the pixels it covered and the rays that
+// were traced for it.
+pub trait Progress: Sync {
+    fn start(&self, total_pixels:usize);
+    fn tile_done(&self, pixels:usize, rays:usize);
+    fn finish(&self);
+}
+
+// for renders nobody is watching
+pub struct Silent;
+
+impl Progress for Silent {
+    fn start(&self, _total_pixels:usize) {}
+    fn tile_done(&self, _pixels:usize, _rays:usize) {}
+    fn finish(&self) {}
+}
+
+// the terminal is redrawn at most this often
+const REDRAW_INTERVAL:Duration = Duration::from_millis(100);
+const BAR_WIDTH:usize = 30;
+
+// a single line progress bar on stderr with percent done, rays per second and
+// the time left if the rest of the image renders at the same pace
+pub struct Bar {
+    total:AtomicUsize,
+    pixels:AtomicUsize,
+    rays:AtomicUsize,
+    started:Mutex<Instant>,
+    drawn:Mutex<Instant>,
+}
+
+impl Default for Bar {
+    fn default() -> Bar {
+        Bar::new()
+    }
+}
+
+impl Bar {
+
+    pub fn new() -> Bar {
+        Bar {total:AtomicUsize::new(0),
+             pixels:AtomicUsize::new(0),
+             rays:AtomicUsize::new(0),
+             started:Mutex::new(Instant::now()),
+             drawn:Mutex::new(Instant::now())}
+    }
+
+    pub fn line(pixels:usize, total:usize, rays:usize, elapsed:Duration) -> String {
+
+        let done = if total == 0 { 1.0 } else { pixels as f64/total as f64 };
+        let secs = elapsed.as_secs_f64();
+
+        let filled = ((done*BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+        let bar    = "=".repeat(filled) + &" ".repeat(BAR_WIDTH - filled);
+
+        let rate = if secs > 0.0 { rays as f64/secs } else { 0.0 };
+        let eta  = if pixels > 0 { secs*(1.0 - done)/done } else { 0.0 };
+
+        format!("[{}] {:5.1}%  {:7.2} Mrays/s  ETA {:.1}s", bar, done*100.0, rate/1e6, eta)
+    }
+
+    fn draw(&self, last:bool) {
+
+        let elapsed = self.started.lock().unwrap().elapsed();
+        let line = Bar::line(self.pixels.load(Ordering::Relaxed),
+                             self.total.load(Ordering::Relaxed),
+                             self.rays.load(Ordering::Relaxed),
+                             elapsed);
+
+        let mut err = std::io::stderr();
+        let _ = write!(err, "\r{}", line);
+        if last {
+            let _ = writeln!(err);
+        }
+        let _ = err.flush();
+    }
+}
+
+impl Progress for Bar {
+
+    fn start(&self, total_pixels:usize) {
+        self.total.store(total_pixels, Ordering::Relaxed);
+        self.pixels.store(0, Ordering::Relaxed);
+        self.rays.store(0, Ordering::Relaxed);
+        *self.started.lock().unwrap() = Instant::now();
+        self.draw(false);
+    }
+
+    fn tile_done(&self, pixels:usize, rays:usize) {
+
+        self.pixels.fetch_add(pixels, Ordering::Relaxed);
+        self.rays.fetch_add(rays, Ordering::Relaxed);
+
+        // whoever holds the lock is already redrawing, no need to wait for it
+        if let Ok(mut drawn) = self.drawn.try_lock() {
+            if drawn.elapsed() >= REDRAW_INTERVAL {
+                *drawn = Instant::now();
+                self.draw(false);
+            }
+        }
+    }
+
+    fn finish(&self) {
+        self.draw(true);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_line() {
+
+        let line = Bar::line(250, 1000, 2_000_000, Duration::from_secs(1));
+
+        assert!(line.starts_with(&format!("[{}{}]", "=".repeat(7), " ".repeat(23))));
+        assert!(line.contains(" 25.0%"));
+        assert!(line.contains("2.00 Mrays/s"));
+        assert!(line.ends_with("ETA 3.0s"));
+    }
+
+    #[test]
+    fn bar_line_edges() {
+
+        assert!(Bar::line(0, 1000, 0, Duration::from_secs(0)).contains("  0.0%"));
+        assert!(Bar::line(1000, 1000, 10, Duration::from_secs(2)).ends_with("100.0%     0.00 Mrays/s  ETA 0.0s"));
+        assert!(Bar::line(0, 0, 0, Duration::from_secs(0)).contains("100.0%"));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::geo;
+use crate::color;
+use crate::canvas;
+use crate::render;
+use crate::progress;
+use std::time::Instant;
+
+// Running sums of every sample taken for each pixel and how many there were,
+// kept beside the canvas the average is resolved into.
+#[derive(Debug,Clone)]
+pub struct Accumulator {
+    width:usize,
+    height:usize,
+    sums:Vec<color::Color>,
+    counts:Vec<u32>,
+}
+
+impl Accumulator {
+
+    pub fn new(width:usize, height:usize) -> Accumulator {
+        Accumulator {width,
+                     height,
+                     sums:vec![color::Color::new(0.0, 0.0, 0.0); width*height],
+                     counts:vec![0; width*height]}
+    }
+
+    // None unless there is one sum and one count per pixel
+    pub fn from_parts(width:usize, height:usize, sums:Vec<color::Color>, counts:Vec<u32>) -> Option<Accumulator> {
+
+        if sums.len() != width*height || counts.len() != width*height {
+            return None;
+        }
+
+        Some(Accumulator {width, height, sums, counts})
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn samples_at(&self, x:usize, y:usize) -> u32 {
+        self.counts[y*self.width + x]
+    }
+
+    pub fn sum_at(&self, x:usize, y:usize) -> color::Color {
+        self.sums[y*self.width + x]
+    }
+
+    // the fewest samples any pixel has, which is the next pass to run
+    pub fn min_samples(&self) -> u32 {
+        self.counts.iter().copied().min().unwrap_or(0)
+    }
+
+    // the mean of the samples so far, black before the first
+    pub fn pixel_at(&self, x:usize, y:usize) -> color::Color {
+
+        let i = y*self.width + x;
+
+        if self.counts[i] == 0 {
+            return color::Color::new(0.0, 0.0, 0.0);
+        }
+
+        self.sums[i]*(1.0/self.counts[i] as geo::Float)
+    }
+
+    // adds sample number `sample` from a pass to the pixels the mask says were
+    // rendered, pixels that already have that sample are left alone
+    pub fn add(&mut self, pass:&canvas::Canvas, mask:&render::Mask, sample:u32) {
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+
+                let i = y*self.width + x;
+
+                if mask.is_done(x, y) && self.counts[i] == sample {
+                    self.sums[i] = self.sums[i] + pass.pixel_at(x, y);
+                    self.counts[i] += 1;
+                }
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> canvas::Canvas {
+
+        let mut image = canvas::Canvas::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                image.write_pixel(x, y, self.pixel_at(x, y));
+            }
+        }
+
+        image
+    }
+}
+
+// Hands each pass's tiles on to the progress of the whole render, whose start
+// and finish are called once around all the passes rather than per pass.
+struct Passes<'a>(&'a dyn progress::Progress);
+
+impl progress::Progress for Passes<'_> {
+    fn start(&self, _total_pixels:usize) {}
+
+    fn tile_done(&self, pixels:usize, rays:usize) {
+        self.0.tile_done(pixels, rays);
+    }
+
+    fn finish(&self) {}
+}
+
+// Renders one sample per pixel per pass and folds each pass into acc, until
+// every pixel has `samples` of them or the settings' time limit or cancel
+// stops it. trace is given the sample number along with the tile, and
+// after_pass sees the accumulator once each pass is in, to snapshot it or
+// report. Starts from whatever acc already holds, so it can carry on a render.
+// progress is told about all the passes as one job.
+// Returns the number of passes run.
+pub fn render<F,P>(acc:&mut Accumulator, samples:u32, settings:&render::Settings,
+                   progress:&dyn progress::Progress, trace:F, mut after_pass:P) -> u32
+    where F: Fn(&mut canvas::Tile, u32) + Sync,
+          P: FnMut(&Accumulator, u32) {
+
+    let deadline = settings.time_limit.map(|l| Instant::now() + l);
+    let mut pass = canvas::Canvas::new(acc.width, acc.height);
+    let mut passes = 0;
+
+    // every pass covers the whole image, so the job is one image per sample
+    // still to take
+    let remaining = samples.saturating_sub(acc.min_samples()) as usize;
+    progress.start(acc.width*acc.height*remaining);
+
+    while acc.min_samples() < samples {
+
+        // each pass gets whatever time is left of the whole render
+        let mut pass_settings = settings.clone();
+        if let Some(d) = deadline {
+            pass_settings.time_limit = Some(d.saturating_duration_since(Instant::now()));
+        }
+
+        let sample = acc.min_samples();
+        let mask   = render::render(&mut pass, &pass_settings, &Passes(progress), |t| trace(t, sample));
+
+        acc.add(&pass, &mask, sample);
+        passes += 1;
+        after_pass(acc, sample);
+
+        if !mask.is_complete() {
+            break;
+        }
+    }
+
+    progress.finish();
+
+    passes
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng;
+    use std::time::Duration;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+
+    // the colour of sample s is s itself, so the mean after n is (n-1)/2
+    fn sample_shade(t:&mut canvas::Tile, sample:u32) {
+        for y in t.y..t.y + t.height {
+            for x in t.x..t.x + t.width {
+                let v = sample as geo::Float;
+                t.write_pixel(x, y, color::Color::new(v, v, v));
+            }
+        }
+    }
+
+    #[test]
+    fn accumulates_to_the_mean() {
+
+        let mut acc = Accumulator::new(9, 5);
+        let settings = render::Settings {tile_size:4, ..render::Settings::default()};
+
+        let mut seen = Vec::new();
+        let passes = render(&mut acc, 4, &settings, &progress::Silent, sample_shade, |a, s| seen.push((s, a.min_samples())));
+
+        assert_eq!(passes, 4);
+        assert_eq!(seen, vec![(0, 1), (1, 2), (2, 3), (3, 4)]);
+        assert_eq!(acc.samples_at(8, 4), 4);
+        assert_eq!(acc.pixel_at(8, 4), color::Color::new(1.5, 1.5, 1.5));
+        assert_eq!(acc.snapshot().pixel_at(0, 0), color::Color::new(1.5, 1.5, 1.5));
+    }
+
+    #[test]
+    fn progress_covers_every_pass() {
+
+        struct Recorder {
+            total:AtomicUsize,
+            pixels:AtomicUsize,
+            starts:AtomicUsize,
+            finishes:AtomicUsize,
+        }
+
+        impl progress::Progress for Recorder {
+            fn start(&self, total_pixels:usize) {
+                self.total.store(total_pixels, Ordering::SeqCst);
+                self.starts.fetch_add(1, Ordering::SeqCst);
+            }
+            fn tile_done(&self, pixels:usize, _rays:usize) {
+                self.pixels.fetch_add(pixels, Ordering::SeqCst);
+            }
+            fn finish(&self) {
+                self.finishes.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let recorder = Recorder {total:AtomicUsize::new(0), pixels:AtomicUsize::new(0),
+                                 starts:AtomicUsize::new(0), finishes:AtomicUsize::new(0)};
+
+        let mut acc = Accumulator::new(9, 5);
+        let settings = render::Settings {tile_size:4, ..render::Settings::default()};
+
+        render(&mut acc, 1, &settings, &progress::Silent, sample_shade, |_, _| {});
+        render(&mut acc, 4, &settings, &recorder, sample_shade, |_, _| {});
+
+        assert_eq!(recorder.starts.load(Ordering::SeqCst), 1);
+        assert_eq!(recorder.finishes.load(Ordering::SeqCst), 1);
+        assert_eq!(recorder.total.load(Ordering::SeqCst), 9*5*3);
+        assert_eq!(recorder.pixels.load(Ordering::SeqCst), 9*5*3);
+    }
+
+    #[test]
+    fn carries_on_from_earlier_samples() {
+
+        let mut acc = Accumulator::new(4, 4);
+        let settings = render::Settings::default();
+
+        render(&mut acc, 2, &settings, &progress::Silent, sample_shade, |_, _| {});
+        let passes = render(&mut acc, 4, &settings, &progress::Silent, sample_shade, |_, _| {});
+
+        assert_eq!(passes, 2);
+        assert_eq!(acc.pixel_at(2, 2), color::Color::new(1.5, 1.5, 1.5));
+    }
+
+    #[test]
+    fn stops_on_time_limit() {
+
+        let mut acc = Accumulator::new(4, 4);
+        let settings = render::Settings {time_limit:Some(Duration::from_secs(0)), ..render::Settings::default()};
+
+        render(&mut acc, 100, &settings, &progress::Silent, sample_shade, |_, _| {});
+
+        assert_eq!(acc.min_samples(), 0);
+        assert_eq!(acc.pixel_at(0, 0), color::Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn partial_pass_is_finished_next_time() {
+
+        let mut acc = Accumulator::new(8, 8);
+        let settings = render::Settings {tile_size:4, threads:1, ..render::Settings::default()};
+        let cancel   = settings.cancel.clone();
+
+        // the first tile of the second pass cancels the render
+        render(&mut acc, 3, &settings, &progress::Silent, |t, s| {
+            if s == 1 {
+                cancel.cancel();
+            }
+            sample_shade(t, s)
+        }, |_, _| {});
+
+        assert_eq!(acc.samples_at(0, 0), 2);
+        assert_eq!(acc.samples_at(7, 7), 1);
+
+        let settings = render::Settings {tile_size:4, threads:1, ..render::Settings::default()};
+        render(&mut acc, 3, &settings, &progress::Silent, sample_shade, |_, _| {});
+
+        assert_eq!(acc.samples_at(0, 0), 3);
+        assert_eq!(acc.pixel_at(0, 0), color::Color::new(1.0, 1.0, 1.0));
+        assert_eq!(acc.pixel_at(7, 7), color::Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn same_seed_same_image_on_any_schedule() {
+
+        let run = |tile_size:usize, threads:usize, seed:u64| {
+
+            let settings = render::Settings {tile_size, threads, seed, ..render::Settings::default()};
+            let mut acc  = Accumulator::new(13, 11);
+
+            render(&mut acc, 3, &settings, &progress::Silent, |t, sample| {
+                for y in t.y..t.y + t.height {
+                    for x in t.x..t.x + t.width {
+                        let mut rng = rng::Pcg32::for_sample(settings.seed, x, y, sample);
+                        t.write_pixel(x, y, color::Color::new(rng.next_float(), rng.next_float(), rng.next_float()));
+                    }
+                }
+            }, |_, _| {});
+
+            // bit for bit, not just within epsilon
+            (0..13*11).map(|i| acc.sum_at(i % 13, i/13))
+                      .flat_map(|c| [c.red.to_bits(), c.green.to_bits(), c.blue.to_bits()])
+                      .collect::<Vec<_>>()
+        };
+
+        let first = run(32, 1, 9);
+
+        assert_eq!(run(3, 4, 9), first);
+        assert_eq!(run(1, 2, 9), first);
+        assert_ne!(run(32, 1, 10), first);
+    }
+}
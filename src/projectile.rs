@@ -4,14 +4,14 @@ use crate::color;
 
 #[derive(Debug,Copy,Clone)]
 pub struct Environment {
-     pub gravity: geo::Geo,
-     pub wind   : geo::Geo
+     pub gravity: geo::Vec3,
+     pub wind   : geo::Vec3
 }
 
 #[derive(Debug,Copy,Clone)]
 pub struct Projectile {
-    pub position : geo::Geo,
-    pub velocity : geo::Geo 
+    pub position : geo::Point3,
+    pub velocity : geo::Vec3 
 }
 
 pub fn tick(env:Environment, proj:Projectile) -> Projectile {
@@ -0,0 +1,222 @@
+use std::ops;
+use crate::geo;
+use crate::matrix;
+
+// past this dot product two rotations are close enough that slerp's
+// division by sin(theta) loses precision, so they are blended linearly
+const SLERP_LINEAR_THRESHOLD:geo::Float = 0.9995;
+
+// unit quaternions represent rotations, w is the scalar part
+#[derive(Debug,Copy,Clone)]
+pub struct Quaternion {
+    pub w:geo::Float,
+    pub x:geo::Float,
+    pub y:geo::Float,
+    pub z:geo::Float,
+}
+
+impl Quaternion {
+
+    pub fn new(w:geo::Float, x:geo::Float, y:geo::Float, z:geo::Float) -> Quaternion {
+        Quaternion {w, x, y, z}
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    // right handed rotation of r radians, matching matrix::Matrix::rotation
+    pub fn from_axis_angle(axis:geo::Vec3, r:geo::Float) -> Quaternion {
+
+        let a = axis.norm();
+        let (s, c) = (r/2.0).sin_cos();
+
+        Quaternion::new(c, a.x*s, a.y*s, a.z*s)
+    }
+
+    // the upper 3x3 of m must be a pure rotation
+    pub fn from_matrix(m:&matrix::Matrix) -> Quaternion {
+
+        let r = |row:usize, col:usize| m.get(row, col);
+        let trace = r(0,0) + r(1,1) + r(2,2);
+
+        // pick the largest of w, x, y, z to divide by, for stability
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt()*2.0;
+            Quaternion::new(0.25*s, (r(2,1) - r(1,2))/s, (r(0,2) - r(2,0))/s, (r(1,0) - r(0,1))/s)
+        } else if r(0,0) > r(1,1) && r(0,0) > r(2,2) {
+            let s = (1.0 + r(0,0) - r(1,1) - r(2,2)).sqrt()*2.0;
+            Quaternion::new((r(2,1) - r(1,2))/s, 0.25*s, (r(0,1) + r(1,0))/s, (r(0,2) + r(2,0))/s)
+        } else if r(1,1) > r(2,2) {
+            let s = (1.0 + r(1,1) - r(0,0) - r(2,2)).sqrt()*2.0;
+            Quaternion::new((r(0,2) - r(2,0))/s, (r(0,1) + r(1,0))/s, 0.25*s, (r(1,2) + r(2,1))/s)
+        } else {
+            let s = (1.0 + r(2,2) - r(0,0) - r(1,1)).sqrt()*2.0;
+            Quaternion::new((r(1,0) - r(0,1))/s, (r(0,2) + r(2,0))/s, (r(1,2) + r(2,1))/s, 0.25*s)
+        };
+
+        q.norm()
+    }
+
+    pub fn to_matrix(self) -> matrix::Matrix {
+
+        let q = self.norm();
+        let (w, x, y, z) = (q.w, q.x, q.y, q.z);
+
+        matrix::Matrix::new([[1.0 - 2.0*(y*y + z*z), 2.0*(x*y - z*w),       2.0*(x*z + y*w),       0.0],
+                             [2.0*(x*y + z*w),       1.0 - 2.0*(x*x + z*z), 2.0*(y*z - x*w),       0.0],
+                             [2.0*(x*z - y*w),       2.0*(y*z + x*w),       1.0 - 2.0*(x*x + y*y), 0.0],
+                             [0.0,                   0.0,                   0.0,                   1.0]])
+    }
+
+    pub fn len(&self) -> geo::Float {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn norm(&self) -> Quaternion {
+        *self*(1.0/self.len())
+    }
+
+    pub fn dot(&self, other:Quaternion) -> geo::Float {
+        self.w*other.w + self.x*other.x + self.y*other.y + self.z*other.z
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    // spherical interpolation along the shorter arc, t = 0 gives self
+    pub fn slerp(&self, other:Quaternion, t:geo::Float) -> Quaternion {
+
+        let a = self.norm();
+        let mut b = other.norm();
+        let mut d = a.dot(b);
+
+        // q and -q are the same rotation, go the short way round
+        if d < 0.0 {
+            b = -b;
+            d = -d;
+        }
+
+        if d > SLERP_LINEAR_THRESHOLD {
+            return (a*(1.0 - t) + b*t).norm();
+        }
+
+        let theta = d.acos();
+        let sin_theta = theta.sin();
+
+        a*(((1.0 - t)*theta).sin()/sin_theta) + b*((t*theta).sin()/sin_theta)
+    }
+}
+
+impl ops::Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, rhs:Quaternion) -> Quaternion {
+        Quaternion::new(self.w + rhs.w, self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl ops::Neg for Quaternion {
+    type Output = Quaternion;
+
+    fn neg(self) -> Quaternion {
+        Quaternion::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+impl ops::Mul<geo::Float> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs:geo::Float) -> Quaternion {
+        Quaternion::new(self.w*rhs, self.x*rhs, self.y*rhs, self.z*rhs)
+    }
+}
+
+// composition, (a*b) rotates by b first and then by a
+impl ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs:Quaternion) -> Quaternion {
+        Quaternion::new(self.w*rhs.w - self.x*rhs.x - self.y*rhs.y - self.z*rhs.z,
+                        self.w*rhs.x + self.x*rhs.w + self.y*rhs.z - self.z*rhs.y,
+                        self.w*rhs.y - self.x*rhs.z + self.y*rhs.w + self.z*rhs.x,
+                        self.w*rhs.z + self.x*rhs.y - self.y*rhs.x + self.z*rhs.w)
+    }
+}
+
+impl PartialEq for Quaternion {
+    fn eq(&self, other: &Self) -> bool {
+        (self.w - other.w).abs() < geo::EPSILON &&
+        (self.x - other.x).abs() < geo::EPSILON &&
+        (self.y - other.y).abs() < geo::EPSILON &&
+        (self.z - other.z).abs() < geo::EPSILON
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::consts::PI;
+
+    #[test]
+    fn axis_angle_matches_matrices() {
+
+        let angles = [PI/6.0, PI/2.0, 2.0*PI/3.0, PI];
+
+        for &a in angles.iter() {
+            assert_eq!(Quaternion::from_axis_angle(geo::Vec3::new(1.0,0.0,0.0), a).to_matrix(), matrix::Matrix::rotate_x(a));
+            assert_eq!(Quaternion::from_axis_angle(geo::Vec3::new(0.0,1.0,0.0), a).to_matrix(), matrix::Matrix::rotate_y(a));
+            assert_eq!(Quaternion::from_axis_angle(geo::Vec3::new(0.0,0.0,1.0), a).to_matrix(), matrix::Matrix::rotate_z(a));
+        }
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+
+        let rotations = vec![
+            matrix::Matrix::identity(),
+            matrix::Matrix::rotate_x(PI),
+            matrix::Matrix::rotate_y(PI),
+            matrix::Matrix::rotate_z(PI),
+            matrix::Matrix::identity().then_rotate_x(0.3).then_rotate_y(-1.2).then_rotate_z(2.5),
+            matrix::Matrix::rotate(geo::Vec3::new(1.0,-2.0,0.5), 3.0),
+        ];
+
+        for m in rotations {
+            assert_eq!(Quaternion::from_matrix(&m).to_matrix(), m);
+        }
+    }
+
+    #[test]
+    fn composition() {
+
+        let a = Quaternion::from_axis_angle(geo::Vec3::new(1.0,0.0,0.0), 0.7);
+        let b = Quaternion::from_axis_angle(geo::Vec3::new(0.0,1.0,0.0),-0.4);
+
+        assert_eq!((a*b).to_matrix(), a.to_matrix()*b.to_matrix());
+        assert_eq!(a*a.conjugate(), Quaternion::identity());
+    }
+
+    #[test]
+    fn slerp_endpoints_and_midpoint() {
+
+        let a = Quaternion::identity();
+        let b = Quaternion::from_axis_angle(geo::Vec3::new(0.0,0.0,1.0), PI/2.0);
+
+        assert_eq!(a.slerp(b, 0.0), a);
+        assert_eq!(a.slerp(b, 1.0), b);
+        assert_eq!(a.slerp(b, 0.5), Quaternion::from_axis_angle(geo::Vec3::new(0.0,0.0,1.0), PI/4.0));
+        assert_eq!(a.slerp(b, 0.5).len(), 1.0);
+    }
+
+    #[test]
+    fn slerp_takes_shorter_arc() {
+
+        let a = Quaternion::from_axis_angle(geo::Vec3::new(0.0,1.0,0.0), 0.1);
+        let b = -Quaternion::from_axis_angle(geo::Vec3::new(0.0,1.0,0.0), 0.3);
+
+        assert_eq!(a.slerp(b, 0.5).to_matrix(), matrix::Matrix::rotate_y(0.2));
+    }
+}
@@ -1,32 +1,40 @@
 use crate::geo;
 use crate::matrix;
 use crate::material;
-use std::f64::consts::PI;
+use crate::bounds;
+#[cfg(test)]
+use geo::consts::PI;
 use std::sync::atomic::{AtomicI32,Ordering};
 use std::cmp::Ordering as Order;
 
 static SHAPE_ID:AtomicI32 = AtomicI32::new(0);
 
+// ids are shared by every kind of shape so an Isect can name any of them
+pub fn next_shape_id() -> i32 {
+    SHAPE_ID.fetch_add(1,Ordering::SeqCst)
+}
+
 #[derive(Debug,Copy,Clone)]
 pub struct Ray {
 
-    pub orig:geo::Geo,
-    pub dir:geo::Geo
+    pub orig:geo::Point3,
+    pub dir:geo::Vec3
 }
 
 impl Ray {
 
-    pub fn new(origin:geo::Geo, direction:geo::Geo) -> Ray {
+    pub fn new(origin:geo::Point3, direction:geo::Vec3) -> Ray {
         
         Ray {orig:origin, dir:direction}
     }
 
-    pub fn position(&self,t:f64) -> geo::Geo {
+    pub fn position(&self,t:geo::Float) -> geo::Point3 {
 
         self.orig + self.dir*t
     }
 
-    pub fn intersect(&self, s:Sphere) -> Vec<Isect> {
+    // both roots against the sphere, None where it is missed
+    fn sphere_roots(&self, s:&Sphere) -> Option<(geo::Float, geo::Float)> {
 
         let r = self.transform(s.transform.inverse());
         let sphere_to_ray = r.orig - s.orig;
@@ -37,21 +45,34 @@ impl Ray {
         let discriminant = b*b - 4.0*a*c;
 
         if discriminant < 0.0 {
-            return vec![]
-        } else {
+            return None;
+        }
 
-            let t1 = (-b - discriminant.sqrt())/(2.0*a);
-            let t2 = (-b + discriminant.sqrt())/(2.0*a);
+        Some(((-b - discriminant.sqrt())/(2.0*a), (-b + discriminant.sqrt())/(2.0*a)))
+    }
+
+    pub fn intersect(&self, s:&Sphere) -> Vec<Isect> {
 
-            return vec![Isect::isect(t1,s.id),
-                        Isect::isect(t2,s.id)]; 
+        match self.sphere_roots(s) {
+            Some((t1, t2)) => vec![Isect::isect(t1,s.id), Isect::isect(t2,s.id)],
+            None           => vec![],
         }
+    }
+
+    // the nearest positive t, infinite where the sphere is missed, without
+    // building the list intersect does
+    pub fn hit(&self, s:&Sphere) -> geo::Float {
 
+        match self.sphere_roots(s) {
+            Some((t1, _)) if t1 > 0.0 => t1,
+            Some((_, t2)) if t2 > 0.0 => t2,
+            _                         => geo::Float::INFINITY,
+        }
     }
 
     pub fn transform(&self,m:matrix::Matrix) -> Ray {
 
-        return Ray::new(m.clone()*self.orig, m.clone()*self.dir);
+        Ray::new(m*self.orig, m*self.dir)
     }
 
 }
@@ -61,10 +82,14 @@ impl Ray {
 pub struct Sphere {
 
         id:i32,
-    pub orig:geo::Geo,
-    pub radius: f64,
-    pub transform: matrix::Matrix,
+    pub orig:geo::Point3,
+    pub radius: geo::Float,
+        transform: matrix::Transform,
     pub material: material::Material,
+    // the enclosing groups' transforms composed into one
+        parent: matrix::Transform,
+    // parent and transform together, object to world space
+        world: matrix::Transform,
 }
 
 
@@ -72,27 +97,75 @@ impl Sphere {
 
     pub fn unit() -> Sphere {
         
-        let id = SHAPE_ID.fetch_add(1,Ordering::SeqCst);
-        Sphere {id:id, 
-                orig: geo::Geo::point(0.0,0.0,0.0),
-                radius:1.0, transform:matrix::Matrix::identity(), 
-                material:material::Material::default()
+        let id = next_shape_id();
+        Sphere {id, 
+                orig: geo::Point3::new(0.0,0.0,0.0),
+                radius:1.0, transform:matrix::Transform::identity(), 
+                material:material::Material::default(),
+                parent:matrix::Transform::identity(),
+                world:matrix::Transform::identity(),
             }
     }
 
-    pub fn set_transform(&mut self, m:matrix::Matrix) {
-        self.transform = m;
+    pub fn with_transform(m:matrix::Matrix) -> Result<Sphere, matrix::TransformError> {
+
+        let mut s = Sphere::unit();
+        s.set_transform(m)?;
+        Ok(s)
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn transform(&self) -> matrix::Matrix {
+        self.transform.matrix()
+    }
+
+    // the cached inverse, world to object space ignoring any parents
+    pub fn inverse(&self) -> matrix::Matrix {
+        self.transform.inverse()
+    }
+
+    pub fn set_transform(&mut self, m:matrix::Matrix) -> Result<(), matrix::TransformError> {
+
+        self.transform = matrix::Transform::new(m)
+            .ok_or_else(|| matrix::TransformError::new("sphere", self.id, m))?;
+
+        self.world = self.parent.compose(&self.transform);
+        Ok(())
+    }
+
+    // world space bounds, the object space box carried through the transform
+    pub fn bounds(&self) -> bounds::Aabb {
+
+        let r   = geo::Vec3::new(self.radius, self.radius, self.radius);
+        let obj = bounds::Aabb::new(self.orig - r, self.orig + r);
+
+        obj.transform(&self.transform.matrix())
+    }
+
+
+    // called by the owning group whenever its place in the hierarchy changes
+    pub fn set_parent(&mut self, parent:matrix::Transform) {
+        self.parent = parent;
+        self.world  = parent.compose(&self.transform);
+    }
+
+    pub fn world_to_object(&self, wld_point:geo::Point3) -> geo::Point3 {
+        self.world.inverse()*wld_point
     }
 
+    pub fn normal_to_world(&self, obj_normal:geo::Vec3) -> geo::Vec3 {
+        (self.world.inverse_transpose()*obj_normal).norm()
+    }
 
-    pub fn normal_at(&self, wld_point:geo::Geo) -> geo::Geo {
+    pub fn normal_at(&self, wld_point:geo::Point3) -> geo::Vec3 {
 
-        let obj_point  = self.transform.inverse()*wld_point;
-        let obj_normal = obj_point - geo::Geo::point(0.0, 0.0, 0.0);
-        let mut wld_normal = self.transform.inverse().transpose()*obj_normal;
-        wld_normal.w = 0.0;
+        let obj_point  = self.world_to_object(wld_point);
+        let obj_normal = obj_point - geo::Point3::new(0.0, 0.0, 0.0);
 
-        return wld_normal.norm();
+        self.normal_to_world(obj_normal)
 
     }
 
@@ -107,15 +180,16 @@ impl Eq for Sphere {}
 
 #[derive(Debug,Copy,Clone)]
 pub struct Isect {
-    pub t: f64,
+    pub t: geo::Float,
     pub id:i32
 }
 
 impl Isect {
 
-    pub fn isect(t:f64,id:i32) -> Isect {
+    #[allow(clippy::self_named_constructors)]
+    pub fn isect(t:geo::Float,id:i32) -> Isect {
 
-        Isect {t:t, id:id }
+        Isect {t, id }
     }
 
     pub fn hit(isects:Vec<Isect>) -> Isect {
@@ -124,7 +198,7 @@ impl Isect {
                     .filter(|i| i.t > 0.0)
                     .min();
 
-        return *ret.unwrap_or(&Isect::isect(0.0,-1));
+        *ret.unwrap_or(&Isect::isect(0.0,-1))
     }
 }
 
@@ -132,7 +206,7 @@ impl Isect {
 impl PartialOrd for Isect {
 
     fn partial_cmp(&self,other: &Self) -> Option<Order> {
-        if self.t < other.t { Some(Order::Less)} else { Some(Order::Greater)}
+        Some(self.cmp(other))
     }
 }
 
@@ -159,25 +233,25 @@ mod tests {
     #[test]
     fn ray() {
 
-        let origin    = geo::Geo::point( 1.0,2.0,3.0);
-        let direction = geo::Geo::vector(4.0,5.0,6.0);
+        let origin    = geo::Point3::new( 1.0,2.0,3.0);
+        let direction = geo::Vec3::new(4.0,5.0,6.0);
 
         let r = Ray::new(origin,direction);
 
-        assert_eq!(r.orig,geo::Geo::point(1.0,2.0,3.0));
-        assert_eq!(r.dir,geo::Geo::vector(4.0,5.0,6.0));
+        assert_eq!(r.orig,geo::Point3::new(1.0,2.0,3.0));
+        assert_eq!(r.dir,geo::Vec3::new(4.0,5.0,6.0));
 
     }
 
     #[test]
     fn ray_dist() {
 
-        let r = Ray::new(geo::Geo::point(2.0,3.0,4.0),geo::Geo::vector(1.0,0.0,0.0));
+        let r = Ray::new(geo::Point3::new(2.0,3.0,4.0),geo::Vec3::new(1.0,0.0,0.0));
         
-        assert_eq!(r.position(0.0), geo::Geo::point(2.0,3.0,4.0));
-        assert_eq!(r.position(1.0), geo::Geo::point(3.0,3.0,4.0));
-        assert_eq!(r.position(-1.0),geo::Geo::point(1.0,3.0,4.0));
-        assert_eq!(r.position(2.5), geo::Geo::point(4.5,3.0,4.0));
+        assert_eq!(r.position(0.0), geo::Point3::new(2.0,3.0,4.0));
+        assert_eq!(r.position(1.0), geo::Point3::new(3.0,3.0,4.0));
+        assert_eq!(r.position(-1.0),geo::Point3::new(1.0,3.0,4.0));
+        assert_eq!(r.position(2.5), geo::Point3::new(4.5,3.0,4.0));
     }
 
 
@@ -185,12 +259,12 @@ mod tests {
     fn intersect1() {
 
         let r = Ray::new(
-            geo::Geo::point( 0.0, 0.0,-5.0),
-            geo::Geo::vector(0.0, 0.0, 1.0));
+            geo::Point3::new( 0.0, 0.0,-5.0),
+            geo::Vec3::new(0.0, 0.0, 1.0));
 
         let s = Sphere::unit();
 
-        let xs = r.intersect(s);
+        let xs = r.intersect(&s);
         
         assert_eq!(xs.len(),2);
         assert_eq!(xs[0].t,4.0);
@@ -202,12 +276,12 @@ mod tests {
     fn intersect2() {
 
         let r = Ray::new(
-            geo::Geo::point( 0.0, 1.0,-5.0),
-            geo::Geo::vector(0.0, 0.0, 1.0));
+            geo::Point3::new( 0.0, 1.0,-5.0),
+            geo::Vec3::new(0.0, 0.0, 1.0));
 
         let s = Sphere::unit();
 
-        let xs = r.intersect(s);
+        let xs = r.intersect(&s);
         
         assert_eq!(xs.len(),2);
         assert_eq!(xs[0].t,5.0);
@@ -219,12 +293,12 @@ mod tests {
     fn intersect3() {
 
         let r = Ray::new(
-            geo::Geo::point( 0.0, 2.0,-5.0),
-            geo::Geo::vector(0.0, 0.0, 1.0));
+            geo::Point3::new( 0.0, 2.0,-5.0),
+            geo::Vec3::new(0.0, 0.0, 1.0));
 
         let s = Sphere::unit();
 
-        let xs = r.intersect(s);
+        let xs = r.intersect(&s);
         
         assert_eq!(xs.len(),0);
     
@@ -234,12 +308,12 @@ mod tests {
     fn intersect4() {
 
         let r = Ray::new(
-            geo::Geo::point( 0.0, 0.0, 0.0),
-            geo::Geo::vector(0.0, 0.0, 1.0));
+            geo::Point3::new( 0.0, 0.0, 0.0),
+            geo::Vec3::new(0.0, 0.0, 1.0));
 
         let s = Sphere::unit();
 
-        let xs = r.intersect(s);
+        let xs = r.intersect(&s);
         
         assert_eq!(xs.len(),2);
         assert_eq!(xs[0].t,-1.0);
@@ -251,12 +325,12 @@ mod tests {
     fn intersect5() {
 
         let r = Ray::new(
-            geo::Geo::point( 0.0, 0.0, 5.0),
-            geo::Geo::vector(0.0, 0.0, 1.0));
+            geo::Point3::new( 0.0, 0.0, 5.0),
+            geo::Vec3::new(0.0, 0.0, 1.0));
 
         let s = Sphere::unit();
 
-        let xs = r.intersect(s);
+        let xs = r.intersect(&s);
         
         assert_eq!(xs.len(),2);
         assert_eq!(xs[0].t,-6.0);
@@ -291,12 +365,12 @@ mod tests {
     fn isect3() {
 
         let r = Ray::new(
-            geo::Geo::point( 0.0, 0.0,-5.0),
-            geo::Geo::vector(0.0, 0.0, 1.0));
+            geo::Point3::new( 0.0, 0.0,-5.0),
+            geo::Vec3::new(0.0, 0.0, 1.0));
 
         let s = Sphere::unit();
         let check = s.id;
-        let xs = r.intersect(s);
+        let xs = r.intersect(&s);
         
         assert_eq!(xs.len(),2);
  
@@ -373,30 +447,30 @@ mod tests {
 fn translate_ray() {
 
     let r = Ray::new(
-        geo::Geo::point( 1.0, 2.0, 3.0),
-        geo::Geo::vector(0.0, 1.0, 0.0));
+        geo::Point3::new( 1.0, 2.0, 3.0),
+        geo::Vec3::new(0.0, 1.0, 0.0));
 
     let m = matrix::Matrix::translate(3.0, 4.0, 5.0);
 
     let r2 = r.transform(m);
 
-    assert_eq!(r2.orig,geo::Geo::point(4.0, 6.0, 8.0));
-    assert_eq!(r2.dir,geo::Geo::vector(0.0, 1.0, 0.0));
+    assert_eq!(r2.orig,geo::Point3::new(4.0, 6.0, 8.0));
+    assert_eq!(r2.dir,geo::Vec3::new(0.0, 1.0, 0.0));
 }
 
 #[test]
 fn scale_ray() {
 
     let r = Ray::new(
-        geo::Geo::point( 1.0, 2.0, 3.0),
-        geo::Geo::vector(0.0, 1.0, 0.0));
+        geo::Point3::new( 1.0, 2.0, 3.0),
+        geo::Vec3::new(0.0, 1.0, 0.0));
 
     let m = matrix::Matrix::scale(2.0, 3.0, 4.0);
 
     let r2 = r.transform(m);
 
-    assert_eq!(r2.orig,geo::Geo::point(2.0, 6.0, 12.0));
-    assert_eq!(r2.dir,geo::Geo::vector(0.0, 3.0, 0.0));
+    assert_eq!(r2.orig,geo::Point3::new(2.0, 6.0, 12.0));
+    assert_eq!(r2.dir,geo::Vec3::new(0.0, 3.0, 0.0));
 }
 
 #[test]
@@ -404,22 +478,22 @@ fn sphere_transform_setting() {
 
     let mut s = Sphere::unit();
     let t = matrix::Matrix::translate(2.0, 3.0, 4.0);
-    s.set_transform(t.clone());
+    s.set_transform(t).unwrap();
 
-    assert_eq!(s.transform,t);
+    assert_eq!(s.transform(),t);
 }
 
 #[test]
 fn scaled_intersection() {
 
     let r = Ray::new(
-        geo::Geo::point( 0.0, 0.0,-5.0),
-        geo::Geo::vector(0.0, 0.0, 1.0));
+        geo::Point3::new( 0.0, 0.0,-5.0),
+        geo::Vec3::new(0.0, 0.0, 1.0));
 
     let mut s = Sphere::unit();
-    s.set_transform(matrix::Matrix::scale(2.0, 2.0, 2.0));
+    s.set_transform(matrix::Matrix::scale(2.0, 2.0, 2.0)).unwrap();
 
-    let xs = r.intersect(s);
+    let xs = r.intersect(&s);
     
     assert_eq!(xs.len(),2);
     assert_eq!(xs[0].t,3.0);
@@ -431,55 +505,72 @@ fn scaled_intersection() {
 fn translated_intersection() {
 
     let r = Ray::new(
-        geo::Geo::point( 0.0, 0.0,-5.0),
-        geo::Geo::vector(0.0, 0.0, 1.0));
+        geo::Point3::new( 0.0, 0.0,-5.0),
+        geo::Vec3::new(0.0, 0.0, 1.0));
 
     let mut s = Sphere::unit();
-    s.set_transform(matrix::Matrix::translate(5.0, 0.0, 0.0));
+    s.set_transform(matrix::Matrix::translate(5.0, 0.0, 0.0)).unwrap();
 
-    let xs = r.intersect(s);
+    let xs = r.intersect(&s);
     
     assert_eq!(xs.len(),0);
 }
 
+#[test]
+fn nearest_hit() {
+
+    let s = Sphere::unit();
+
+    let outside = Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+    let inside  = Ray::new(geo::Point3::new(0.0, 0.0, 0.0), geo::Vec3::new(0.0, 0.0, 1.0));
+    let behind  = Ray::new(geo::Point3::new(0.0, 0.0, 5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+    let miss    = Ray::new(geo::Point3::new(0.0, 2.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+
+    assert_eq!(outside.hit(&s), 4.0);
+    assert_eq!(inside.hit(&s), 1.0);
+    assert_eq!(behind.hit(&s), geo::Float::INFINITY);
+    assert_eq!(miss.hit(&s), geo::Float::INFINITY);
+}
+
 #[test]
 fn normal_x_axis() {
     let s = Sphere::unit();
-    assert_eq!(s.normal_at(geo::Geo::point(1.0, 0.0, 0.0)),geo::Geo::vector(1.0, 0.0, 0.0));
+    assert_eq!(s.normal_at(geo::Point3::new(1.0, 0.0, 0.0)),geo::Vec3::new(1.0, 0.0, 0.0));
 }
 
 #[test]
 fn normal_y_axis() {
     let s = Sphere::unit();
-    assert_eq!(s.normal_at(geo::Geo::point(0.0, 1.0, 0.0)),geo::Geo::vector(0.0, 1.0, 0.0));
+    assert_eq!(s.normal_at(geo::Point3::new(0.0, 1.0, 0.0)),geo::Vec3::new(0.0, 1.0, 0.0));
 }
 
 #[test]
 fn normal_z_axis() {
     let s = Sphere::unit();
-    assert_eq!(s.normal_at(geo::Geo::point(0.0, 0.0, 1.0)),geo::Geo::vector(0.0, 0.0, 1.0));
+    assert_eq!(s.normal_at(geo::Point3::new(0.0, 0.0, 1.0)),geo::Vec3::new(0.0, 0.0, 1.0));
 }
 
 #[test]
 fn normal_non_axial() {
     let s = Sphere::unit();
-    assert_eq!(s.normal_at(geo::Geo::point((3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0)),
-                          geo::Geo::vector((3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0));
+    assert_eq!(s.normal_at(geo::Point3::new((3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0)),
+                          geo::Vec3::new((3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0));
 }
 
 #[test]
 fn normalized_normal() {
     let s = Sphere::unit();
-    let n = s.normal_at(geo::Geo::point((3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0));
-    assert_eq!(n.norm(),geo::Geo::vector((3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0,(3.0 as f64).sqrt()/3.0));
+    let n = s.normal_at(geo::Point3::new((3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0));
+    assert_eq!(n.norm(),geo::Vec3::new((3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0,(3.0 as geo::Float).sqrt()/3.0));
 }
 
 #[test]
+#[allow(clippy::approx_constant)]
 fn translated_normal() {
     let mut s = Sphere::unit();
-    s.set_transform(matrix::Matrix::translate(0.0, 1.0, 0.0));
-    let n = s.normal_at(geo::Geo::point(0.0, 1.70711, -0.70711));
-    assert_eq!(n,geo::Geo::vector(0.0, 0.70711, -0.70711));
+    s.set_transform(matrix::Matrix::translate(0.0, 1.0, 0.0)).unwrap();
+    let n = s.normal_at(geo::Point3::new(0.0, 1.70711, -0.70711));
+    assert_eq!(n,geo::Vec3::new(0.0, 0.70711, -0.70711));
 }
 
 #[test]
@@ -487,8 +578,53 @@ fn scaled_rotated_normal() {
 
     let mut s = Sphere::unit();
     let m = matrix::Matrix::scale(1.0, 0.5, 1.0) * matrix::Matrix::rotate_z(PI/5.0);
-    s.set_transform(m);
-    let n = s.normal_at(geo::Geo::point(0.0, 1.0/(2.0 as f64).sqrt(), -1.0/(2.0 as f64).sqrt()));
-    assert_eq!(n,geo::Geo::vector(0.0, 0.97014, -0.24254));
+    s.set_transform(m).unwrap();
+    let n = s.normal_at(geo::Point3::new(0.0, 1.0/(2.0 as geo::Float).sqrt(), -1.0/(2.0 as geo::Float).sqrt()));
+    assert_eq!(n,geo::Vec3::new(0.0, 0.97014, -0.24254));
+
+}
+#[test]
+fn sphere_bounds() {
+
+    let mut s = Sphere::unit();
+    assert_eq!(s.bounds().min, geo::Point3::new(-1.0,-1.0,-1.0));
+    assert_eq!(s.bounds().max, geo::Point3::new( 1.0, 1.0, 1.0));
+
+    s.set_transform(matrix::Matrix::translate(1.0,-3.0, 5.0) * matrix::Matrix::scale(0.5, 2.0, 4.0)).unwrap();
+    assert_eq!(s.bounds().min, geo::Point3::new(0.5,-5.0, 1.0));
+    assert_eq!(s.bounds().max, geo::Point3::new(1.5,-1.0, 9.0));
+}
 
-}
\ No newline at end of file
+#[test]
+fn retransformed_sphere() {
+
+    // the cached inverse must follow every set_transform
+    let mut s = Sphere::unit();
+    s.set_transform(matrix::Matrix::translate(0.0, 1.0, 0.0)).unwrap();
+    s.set_transform(matrix::Matrix::scale(2.0, 2.0, 2.0)).unwrap();
+
+    let r  = Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0));
+    let xs = r.intersect(&s);
+
+    assert_eq!(xs[0].t, 3.0);
+    assert_eq!(s.normal_at(geo::Point3::new(0.0, 0.0,-2.0)), geo::Vec3::new(0.0, 0.0,-1.0));
+}
+
+#[test]
+fn singular_transform_rejected() {
+
+    let mut s = Sphere::unit();
+    s.set_transform(matrix::Matrix::translate(1.0, 0.0, 0.0)).unwrap();
+
+    let err = s.set_transform(matrix::Matrix::scale(0.0, 1.0, 1.0)).unwrap_err();
+
+    assert_eq!(err.kind, "sphere");
+    assert_eq!(err.id, s.id);
+    assert_eq!(err.to_string(), format!("sphere {} was given a non-invertible transform (det = 0)", s.id));
+    // the previous transform is kept
+    assert_eq!(s.transform(), matrix::Matrix::translate(1.0, 0.0, 0.0));
+
+    assert!(Sphere::with_transform(matrix::Matrix::shear(1.0, 0.0, 0.0, 0.0, 0.0, 1.0)).is_ok());
+    assert!(Sphere::with_transform(matrix::Matrix::scale(1e-4, 1e-4, 1e-4)).is_ok());
+    assert!(Sphere::with_transform(matrix::Matrix::shear(1.0, 0.0, 1.0 - 1e-13, 0.0, 0.0, 0.0)).is_err());
+}
@@ -0,0 +1,325 @@
+use crate::color;
+use crate::canvas;
+use crate::progress;
+use rayon::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::time::{Duration,Instant};
+
+#[derive(Debug,Clone)]
+pub struct Settings {
+    // tiles are tile_size pixels square, less at the right and bottom edges
+    pub tile_size:usize,
+    // worker threads, 0 uses rayon's global pool with one per core
+    pub threads:usize,
+    // no new tiles are started once this much time has passed
+    pub time_limit:Option<Duration>,
+    // or once this is cancelled
+    pub cancel:Cancel,
+    // every random number a sampler draws comes from this and the pixel and
+    // sample it is for, see rng::Pcg32::for_sample
+    pub seed:u64,
+}
+
+impl Default for Settings {
+
+    fn default() -> Settings {
+        Settings {tile_size:32, threads:0, time_limit:None, cancel:Cancel::new(), seed:0}
+    }
+}
+
+// Stops a render from another thread. Clones share the same flag, so keep one
+// and hand a clone to the settings.
+#[derive(Debug,Clone,Default)]
+pub struct Cancel {
+    flag:Arc<AtomicBool>,
+}
+
+impl Cancel {
+
+    pub fn new() -> Cancel {
+        Cancel {flag:Arc::new(AtomicBool::new(false))}
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+// which pixels a render finished, all of them unless it was stopped early
+#[derive(Debug,Clone)]
+pub struct Mask {
+    width:usize,
+    done:Vec<bool>,
+}
+
+impl Mask {
+
+    fn new(width:usize, height:usize) -> Mask {
+        Mask {width, done:vec![false; width*height]}
+    }
+
+    fn mark(&mut self, x:usize, y:usize, width:usize, height:usize) {
+        for row in y..y + height {
+            for d in self.done[row*self.width + x..row*self.width + x + width].iter_mut() {
+                *d = true;
+            }
+        }
+    }
+
+    pub fn is_done(&self, x:usize, y:usize) -> bool {
+        self.done[y*self.width + x]
+    }
+
+    pub fn count(&self) -> usize {
+        self.done.iter().filter(|&&d| d).count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.done.iter().all(|&d| d)
+    }
+}
+
+// Splits the canvas into tiles and hands them to rayon, whose workers steal
+// tiles from each other as they run dry. trace fills in every pixel of the
+// tile it is given, writing straight into the canvas, and may count the rays
+// it traced in tile.rays; one per pixel is assumed when it leaves it at zero.
+//
+// Workers check for cancellation and the time limit before each tile, tiles
+// already underway are finished. The returned mask tells which pixels made it.
+pub fn render<F>(image:&mut canvas::Canvas, settings:&Settings, progress:&dyn progress::Progress, trace:F) -> Mask
+    where F: Fn(&mut canvas::Tile) + Sync {
+
+    render_layers(std::slice::from_mut(image), settings, progress, |t| trace(&mut t[0]))
+}
+
+// render into several canvases of the same size at once, trace is given the
+// same tile of each in the order of images. Rays are counted from the first.
+pub fn render_layers<F>(images:&mut [canvas::Canvas], settings:&Settings, progress:&dyn progress::Progress, trace:F) -> Mask
+    where F: Fn(&mut [canvas::Tile]) + Sync {
+
+    assert!(!images.is_empty(), "nothing to render into");
+
+    let (width, height) = (images[0].get_width(), images[0].get_height());
+    assert!(images.iter().all(|i| i.get_width() == width && i.get_height() == height),
+            "layers must all be the same size");
+
+    let deadline = settings.time_limit.map(|l| Instant::now() + l);
+
+    progress.start(width*height);
+
+    // the same tile of every layer together
+    let mut layers:Vec<_> = images.iter_mut().map(|i| i.tiles(settings.tile_size).into_iter()).collect();
+    let count = layers[0].len();
+    let tiles:Vec<Vec<canvas::Tile>> = (0..count)
+        .map(|_| layers.iter_mut().map(|l| l.next().unwrap()).collect())
+        .collect();
+
+    let run = || tiles.into_par_iter().filter_map(|mut t| {
+
+        if settings.cancel.is_cancelled() || matches!(deadline, Some(d) if Instant::now() >= d) {
+            return None;
+        }
+
+        trace(&mut t);
+
+        let t = &t[0];
+        let pixels = t.width*t.height;
+        progress.tile_done(pixels, if t.rays == 0 { pixels } else { t.rays });
+
+        Some((t.x, t.y, t.width, t.height))
+    })
+    .collect::<Vec<_>>();
+
+    let finished = if settings.threads == 0 {
+        run()
+    } else {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(settings.threads)
+            .build()
+            .expect("failed to build thread pool")
+            .install(run)
+    };
+
+    progress.finish();
+
+    let mut mask = Mask::new(width, height);
+    for (x, y, w, h) in finished {
+        mask.mark(x, y, w, h);
+    }
+
+    mask
+}
+
+// render for when each pixel can be worked out on its own
+pub fn render_pixels<F>(image:&mut canvas::Canvas, settings:&Settings, progress:&dyn progress::Progress, shade:F) -> Mask
+    where F: Fn(usize, usize) -> color::Color + Sync {
+
+    render(image, settings, progress, |t| {
+        for y in t.y..t.y + t.height {
+            for x in t.x..t.x + t.width {
+                t.write_pixel(x, y, shade(x, y));
+            }
+        }
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo;
+    use std::sync::atomic::AtomicUsize;
+
+    fn gradient(x:usize, y:usize) -> color::Color {
+        color::Color::new(x as geo::Float, y as geo::Float, (x*y) as geo::Float)
+    }
+
+    #[test]
+    fn every_pixel_rendered() {
+
+        for &(tile_size, threads) in [(32, 0), (5, 1), (7, 3), (1, 2)].iter() {
+
+            let mut image = canvas::Canvas::new(37, 21);
+            let settings = Settings {tile_size, threads, ..Settings::default()};
+            let mask     = render_pixels(&mut image, &settings, &progress::Silent, gradient);
+
+            assert!(mask.is_complete());
+
+            for y in 0..21 {
+                for x in 0..37 {
+                    assert_eq!(image.pixel_at(x, y), gradient(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn thread_count_is_respected() {
+
+        let mut image = canvas::Canvas::new(16, 16);
+        let settings  = Settings {tile_size:4, threads:3, ..Settings::default()};
+
+        render(&mut image, &settings, &progress::Silent, |_| assert_eq!(rayon::current_num_threads(), 3));
+    }
+
+    // records what it is told so the calls can be checked
+    struct Recorder {
+        total:AtomicUsize,
+        pixels:AtomicUsize,
+        rays:AtomicUsize,
+        tiles:AtomicUsize,
+        finished:AtomicUsize,
+    }
+
+    impl progress::Progress for Recorder {
+
+        fn start(&self, total_pixels:usize) {
+            self.total.store(total_pixels, Ordering::SeqCst);
+        }
+
+        fn tile_done(&self, pixels:usize, rays:usize) {
+            self.pixels.fetch_add(pixels, Ordering::SeqCst);
+            self.rays.fetch_add(rays, Ordering::SeqCst);
+            self.tiles.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn finish(&self) {
+            self.finished.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn progress_is_reported_per_tile() {
+
+        let recorder = Recorder {total:AtomicUsize::new(0), pixels:AtomicUsize::new(0), rays:AtomicUsize::new(0),
+                                 tiles:AtomicUsize::new(0), finished:AtomicUsize::new(0)};
+
+        let mut image = canvas::Canvas::new(10, 10);
+        let settings  = Settings {tile_size:4, threads:2, ..Settings::default()};
+
+        // tiles on the left edge claim two rays per pixel, the rest say nothing
+        render(&mut image, &settings, &recorder, |t| {
+            if t.x == 0 {
+                t.rays = 2*t.width*t.height;
+            }
+        });
+
+        assert_eq!(recorder.total.load(Ordering::SeqCst), 100);
+        assert_eq!(recorder.pixels.load(Ordering::SeqCst), 100);
+        assert_eq!(recorder.rays.load(Ordering::SeqCst), 100 + 4*10);
+        assert_eq!(recorder.tiles.load(Ordering::SeqCst), 9);
+        assert_eq!(recorder.finished.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn cancelled_before_starting() {
+
+        let mut image = canvas::Canvas::new(8, 8);
+        let settings  = Settings::default();
+
+        settings.cancel.clone().cancel();
+        let mask = render_pixels(&mut image, &settings, &progress::Silent, gradient);
+
+        assert_eq!(mask.count(), 0);
+        assert!(!mask.is_complete());
+        assert_eq!(image.pixel_at(3, 3), color::Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cancelled_part_way() {
+
+        let mut image = canvas::Canvas::new(8, 8);
+        let settings  = Settings {tile_size:4, threads:1, ..Settings::default()};
+        let cancel    = settings.cancel.clone();
+
+        // a single worker takes tiles in order, so only the first is finished
+        let mask = render_pixels(&mut image, &settings, &progress::Silent, |x, y| {
+            cancel.cancel();
+            gradient(x, y)
+        });
+
+        assert_eq!(mask.count(), 16);
+        assert!(mask.is_done(3, 3));
+        assert!(!mask.is_done(4, 0));
+        assert_eq!(image.pixel_at(3, 3), gradient(3, 3));
+        assert_eq!(image.pixel_at(4, 0), color::Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn layers_share_tiles() {
+
+        let mut layers = vec![canvas::Canvas::new(9, 7), canvas::Canvas::new(9, 7)];
+        let settings   = Settings {tile_size:4, threads:2, ..Settings::default()};
+
+        let mask = render_layers(&mut layers, &settings, &progress::Silent, |t| {
+            let (x0, y0) = (t[0].x, t[0].y);
+            for y in y0..y0 + t[0].height {
+                for x in x0..x0 + t[0].width {
+                    t[0].write_pixel(x, y, gradient(x, y));
+                    t[1].write_pixel(x, y, gradient(y, x));
+                }
+            }
+        });
+
+        assert!(mask.is_complete());
+        assert_eq!(layers[0].pixel_at(8, 6), gradient(8, 6));
+        assert_eq!(layers[1].pixel_at(8, 6), gradient(6, 8));
+    }
+
+    #[test]
+    fn time_limit() {
+
+        let mut image = canvas::Canvas::new(8, 8);
+
+        let none = Settings {time_limit:Some(Duration::from_secs(0)), ..Settings::default()};
+        assert_eq!(render_pixels(&mut image, &none, &progress::Silent, gradient).count(), 0);
+
+        let ample = Settings {time_limit:Some(Duration::from_secs(600)), ..Settings::default()};
+        assert!(render_pixels(&mut image, &ample, &progress::Silent, gradient).is_complete());
+    }
+}
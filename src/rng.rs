@@ -0,0 +1,129 @@
+use crate::geo;
+
+const PCG_MULTIPLIER:u64 = 6364136223846793005;
+
+// PCG32 (XSH RR), small and fast with good statistical quality. Each sample of
+// each pixel gets its own generator from for_sample, so what a pixel draws
+// never depends on which thread rendered it or in what order.
+#[derive(Debug,Copy,Clone)]
+pub struct Pcg32 {
+    state:u64,
+    inc:u64,
+}
+
+impl Pcg32 {
+
+    // same seeding as the reference pcg32_srandom_r, any stream gives an
+    // independent sequence for the same seed
+    pub fn new(seed:u64, stream:u64) -> Pcg32 {
+
+        let mut rng = Pcg32 {state:0, inc:(stream << 1) | 1};
+
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+
+        rng
+    }
+
+    // the generator for sample number `sample` of pixel (x,y) in a render
+    // seeded with `seed`
+    pub fn for_sample(seed:u64, x:usize, y:usize, sample:u32) -> Pcg32 {
+
+        let pixel = ((y as u64) << 32) | (x as u64 & 0xffff_ffff);
+
+        Pcg32::new(splitmix64(seed ^ splitmix64(pixel)), sample as u64)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.inc);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+
+        xorshifted.rotate_right(rot)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    // uniform in [0, 1), using as many bits as the float type can hold so the
+    // result can never round up to 1
+    pub fn next_float(&mut self) -> geo::Float {
+
+        let bits = geo::Float::MANTISSA_DIGITS;
+        let scale = (0.5 as geo::Float).powi(bits as i32);
+
+        (self.next_u64() >> (64 - bits)) as geo::Float*scale
+    }
+
+    // uniform in [lo, hi)
+    pub fn range(&mut self, lo:geo::Float, hi:geo::Float) -> geo::Float {
+        lo + (hi - lo)*self.next_float()
+    }
+}
+
+// a strong 64 bit mix, used to spread nearby seeds and pixels apart
+fn splitmix64(v:u64) -> u64 {
+
+    let mut z = v.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    z ^ (z >> 31)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_sequence() {
+
+        // from the pcg32 demo program, seeded with 42 on stream 54
+        let mut rng = Pcg32::new(42, 54);
+        let expected = [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e];
+
+        for &e in expected.iter() {
+            assert_eq!(rng.next_u32(), e);
+        }
+    }
+
+    #[test]
+    fn floats_in_unit_interval() {
+
+        let mut rng = Pcg32::new(7, 0);
+        let mut sum = 0.0;
+
+        for _ in 0..10000 {
+            let f = rng.next_float();
+            assert!((0.0..1.0).contains(&f));
+            sum += f;
+        }
+
+        assert!((sum/10000.0 - 0.5).abs() < 0.02);
+
+        let r = rng.range(-2.0, 3.0);
+        assert!((-2.0..3.0).contains(&r));
+    }
+
+    #[test]
+    fn samples_are_independent_of_order() {
+
+        let a = Pcg32::for_sample(1, 10, 20, 3).next_u64();
+
+        // drawing other pixels and samples first changes nothing
+        for s in 0..4 {
+            Pcg32::for_sample(1, 20, 10, s).next_u64();
+        }
+
+        assert_eq!(Pcg32::for_sample(1, 10, 20, 3).next_u64(), a);
+        assert_ne!(Pcg32::for_sample(1, 20, 10, 3).next_u64(), a);
+        assert_ne!(Pcg32::for_sample(1, 10, 20, 4).next_u64(), a);
+        assert_ne!(Pcg32::for_sample(2, 10, 20, 3).next_u64(), a);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::geo;
+use crate::rng;
+use geo::consts::PI;
+
+// Directions drawn for Monte Carlo integration, each with the pdf it was drawn
+// with so integrators can weight the sample.
+
+// two unit vectors that make an orthonormal frame with the unit normal n,
+// without the branch on the largest component (Duff et al. 2017)
+pub fn basis(n:geo::Vec3) -> (geo::Vec3, geo::Vec3) {
+
+    let sign = (1.0 as geo::Float).copysign(n.z);
+    let a    = -1.0/(sign + n.z);
+    let b    = n.x*n.y*a;
+
+    (geo::Vec3::new(1.0 + sign*n.x*n.x*a, sign*b, -sign*n.x),
+     geo::Vec3::new(b, sign + n.y*n.y*a, -n.y))
+}
+
+// a direction in the hemisphere about n with density cos(theta)/pi, which
+// cancels the cosine and the 1/pi of a lambertian surface
+pub fn cosine_hemisphere(n:geo::Vec3, rng:&mut rng::Pcg32) -> geo::Vec3 {
+
+    let (u1, u2) = (rng.next_float(), rng.next_float());
+
+    let r   = u1.sqrt();
+    let phi = 2.0*PI*u2;
+    let (t, b) = basis(n);
+
+    t*(r*phi.cos()) + b*(r*phi.sin()) + n*(1.0 - u1).max(0.0).sqrt()
+}
+
+pub fn cosine_hemisphere_pdf(n:geo::Vec3, wi:geo::Vec3) -> geo::Float {
+    n.dot(wi).max(0.0)/PI
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basis_is_orthonormal() {
+
+        for n in [geo::Vec3::new(0.0, 0.0, 1.0), geo::Vec3::new(0.0, 0.0, -1.0),
+                  geo::Vec3::new(1.0, 2.0, -3.0).norm(), geo::Vec3::new(-0.3, 0.1, 0.2).norm()].iter() {
+
+            let (t, b) = basis(*n);
+
+            assert!((t.len() - 1.0).abs() < geo::EPSILON);
+            assert!((b.len() - 1.0).abs() < geo::EPSILON);
+            assert!(t.dot(b).abs() < geo::EPSILON);
+            assert!(t.dot(*n).abs() < geo::EPSILON);
+            assert!(b.dot(*n).abs() < geo::EPSILON);
+        }
+    }
+
+    #[test]
+    fn cosine_samples_stay_above_the_surface() {
+
+        let n = geo::Vec3::new(1.0, -1.0, 0.5).norm();
+        let mut rng = rng::Pcg32::new(3, 0);
+        let mut mean_cos = 0.0;
+
+        for _ in 0..10000 {
+            let wi = cosine_hemisphere(n, &mut rng);
+            assert!((wi.len() - 1.0).abs() < geo::EPSILON);
+            assert!(wi.dot(n) >= 0.0);
+            assert!(cosine_hemisphere_pdf(n, wi) >= 0.0);
+            mean_cos += wi.dot(n)/10000.0;
+        }
+
+        // the mean of cos(theta) under a cosine density is 2/3
+        assert!((mean_cos - 2.0/3.0).abs() < 0.01);
+    }
+}
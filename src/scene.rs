@@ -0,0 +1,138 @@
+use crate::geo;
+use crate::ray;
+use crate::bvh;
+use crate::shape;
+use crate::light;
+use crate::material;
+use crate::environment;
+
+// the shapes of a scene, kept in a bvh, the lights shining on them and what
+// surrounds them
+#[derive(Debug,Clone)]
+pub struct Scene {
+    bvh:bvh::Bvh,
+    pub lights:Vec<light::Light>,
+    pub environment:environment::Environment,
+}
+
+// what an integrator needs to know about the surface a ray hit
+#[derive(Debug,Copy,Clone)]
+pub struct Hit {
+    pub t:geo::Float,
+    pub id:i32,
+    pub point:geo::Point3,
+    // unit surface normal, flipped to face back along the ray
+    pub normal:geo::Vec3,
+    // true if the ray came from outside the surface
+    pub front:bool,
+    pub material:material::Material,
+}
+
+impl Hit {
+
+    // just off the surface on the side the ray came from, where rays leaving
+    // the surface start so they do not hit it again straight away
+    pub fn over_point(&self) -> geo::Point3 {
+        self.point + self.normal*geo::EPSILON
+    }
+}
+
+impl Scene {
+
+    // in a black environment until told otherwise
+    pub fn new(shapes:Vec<shape::Shape>, lights:Vec<light::Light>) -> Scene {
+        Scene {bvh:bvh::Bvh::build(shapes), lights, environment:environment::Environment::black()}
+    }
+
+    pub fn bvh(&self) -> &bvh::Bvh {
+        &self.bvh
+    }
+
+    // the nearest surface along r, if any
+    pub fn hit(&self, r:&ray::Ray) -> Option<Hit> {
+        self.surface(r, self.bvh.hit(r, &mut bvh::Stats::default()))
+    }
+
+    // the surface behind an intersection found some other way, such as a packet
+    // traced through the bvh, None for a miss
+    pub fn surface(&self, r:&ray::Ray, isect:ray::Isect) -> Option<Hit> {
+
+        if isect.id < 0 {
+            return None;
+        }
+
+        let shape  = self.bvh.shape(isect.id)?;
+        let point  = r.position(isect.t);
+        let normal = shape.normal_at(point)?;
+        let front  = normal.dot(r.dir) < 0.0;
+
+        Some(Hit {t:isect.t,
+                  id:isect.id,
+                  point,
+                  normal:if front { normal } else { -normal },
+                  front,
+                  material:shape.material()?})
+    }
+
+    // true if anything lies along r, however far away
+    pub fn blocked(&self, r:&ray::Ray) -> bool {
+        self.bvh.hit(r, &mut bvh::Stats::default()).id >= 0
+    }
+
+    // true if anything lies on the segment between the two points
+    pub fn occluded(&self, from:geo::Point3, to:geo::Point3) -> bool {
+
+        let r = ray::Ray::new(from, to - from);
+        let h = self.bvh.hit(&r, &mut bvh::Stats::default());
+
+        h.id >= 0 && h.t < 1.0 - geo::EPSILON
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+    use crate::matrix;
+
+    fn two_spheres() -> Scene {
+
+        let near = ray::Sphere::unit();
+        let far  = ray::Sphere::with_transform(matrix::Matrix::translate(0.0, 0.0, 5.0)).unwrap();
+        let light = light::Light::point(color::Color::new(1.0, 1.0, 1.0), geo::Point3::new(0.0, 0.0, -10.0));
+
+        Scene::new(vec![shape::Shape::from(near), shape::Shape::from(far)], vec![light])
+    }
+
+    #[test]
+    fn hit_from_outside_and_inside() {
+
+        let scene = two_spheres();
+
+        let outside = scene.hit(&ray::Ray::new(geo::Point3::new(0.0, 0.0, -5.0), geo::Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        assert_eq!(outside.t, 4.0);
+        assert!(outside.front);
+        assert_eq!(outside.normal, geo::Vec3::new(0.0, 0.0, -1.0));
+        assert!(outside.over_point().z < -1.0);
+
+        let inside = scene.hit(&ray::Ray::new(geo::Point3::origin(), geo::Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        assert_eq!(inside.t, 1.0);
+        assert!(!inside.front);
+        assert_eq!(inside.normal, geo::Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(scene.hit(&ray::Ray::new(geo::Point3::new(0.0, 5.0, -5.0), geo::Vec3::new(0.0, 0.0, 1.0))).is_none());
+    }
+
+    #[test]
+    fn occlusion() {
+
+        let scene = two_spheres();
+        let light = scene.lights[0].position;
+
+        // the near sphere shadows the far one, nothing shadows its own lit side
+        assert!(scene.occluded(geo::Point3::new(0.0, 0.0, 3.99), light));
+        assert!(!scene.occluded(geo::Point3::new(0.0, 0.0, -1.0 - geo::EPSILON), light));
+        assert!(!scene.occluded(geo::Point3::new(0.0, 5.0, 0.0), light));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::geo;
+use crate::ray;
+use crate::csg;
+use crate::group;
+use crate::matrix;
+use crate::bounds;
+use crate::material;
+
+// anything that can be placed in a scene, either a primitive with a surface
+// or a container whose intersections come from its children
+// spheres carry their cached transforms inline rather than boxed, they are
+// the ones every ray tests
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug,Clone)]
+pub enum Shape {
+    Sphere(ray::Sphere),
+    Group(group::Group),
+    Csg(csg::Csg),
+}
+
+impl Shape {
+
+    pub fn id(&self) -> i32 {
+        match self {
+            Shape::Sphere(s) => s.id(),
+            Shape::Group(g)  => g.id(),
+            Shape::Csg(c)    => c.id(),
+        }
+    }
+
+    pub fn transform(&self) -> matrix::Matrix {
+        match self {
+            Shape::Sphere(s) => s.transform(),
+            Shape::Group(g)  => g.transform(),
+            Shape::Csg(c)    => c.transform(),
+        }
+    }
+
+    pub fn set_transform(&mut self, m:matrix::Matrix) -> Result<(), matrix::TransformError> {
+        match self {
+            Shape::Sphere(s) => s.set_transform(m),
+            Shape::Group(g)  => g.set_transform(m),
+            Shape::Csg(c)    => c.set_transform(m),
+        }
+    }
+
+    pub fn set_parent(&mut self, parent:matrix::Transform) {
+        match self {
+            Shape::Sphere(s) => s.set_parent(parent),
+            Shape::Group(g)  => g.set_parent(parent),
+            Shape::Csg(c)    => c.set_parent(parent),
+        }
+    }
+
+    // bounds in the space of whatever contains this shape
+    pub fn bounds(&self) -> bounds::Aabb {
+        match self {
+            Shape::Sphere(s) => s.bounds(),
+            Shape::Group(g)  => g.bounds(),
+            Shape::Csg(c)    => c.bounds(),
+        }
+    }
+
+    // intersections sorted by t, ids name the primitive that was hit
+    pub fn intersect(&self, r:&ray::Ray) -> Vec<ray::Isect> {
+        match self {
+            Shape::Sphere(s) => r.intersect(s),
+            Shape::Group(g)  => g.intersect(r),
+            Shape::Csg(c)    => c.intersect(r),
+        }
+    }
+
+    // true if this shape is, or contains, the shape with the given id
+    pub fn includes(&self, id:i32) -> bool {
+        self.find(id).is_some()
+    }
+
+    pub fn find(&self, id:i32) -> Option<&Shape> {
+
+        if self.id() == id {
+            return Some(self);
+        }
+
+        self.children().into_iter().find_map(|c| c.find(id))
+    }
+
+    // the shapes directly contained by a group or csg, empty for primitives
+    pub fn children(&self) -> Vec<&Shape> {
+        match self {
+            Shape::Sphere(_) => vec![],
+            Shape::Group(g)  => g.children().iter().collect(),
+            Shape::Csg(c)    => vec![c.left(), c.right()],
+        }
+    }
+
+    // only primitives have a surface, None for a group or csg, whose hits
+    // name the primitive that was hit to look up with find
+    pub fn normal_at(&self, wld_point:geo::Point3) -> Option<geo::Vec3> {
+        match self {
+            Shape::Sphere(s) => Some(s.normal_at(wld_point)),
+            Shape::Group(_) | Shape::Csg(_) => None,
+        }
+    }
+
+    // likewise only primitives have a material
+    pub fn material(&self) -> Option<material::Material> {
+        match self {
+            Shape::Sphere(s) => Some(s.material),
+            Shape::Group(_) | Shape::Csg(_) => None,
+        }
+    }
+}
+
+impl From<ray::Sphere> for Shape {
+    fn from(s:ray::Sphere) -> Shape {
+        Shape::Sphere(s)
+    }
+}
+
+impl From<group::Group> for Shape {
+    fn from(g:group::Group) -> Shape {
+        Shape::Group(g)
+    }
+}
+
+impl From<csg::Csg> for Shape {
+    fn from(c:csg::Csg) -> Shape {
+        Shape::Csg(c)
+    }
+}
+
+impl PartialEq for Shape {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+impl Eq for Shape {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_in_hierarchy() {
+
+        let s1 = ray::Sphere::unit();
+        let s2 = ray::Sphere::unit();
+        let (id1, id2) = (s1.id(), s2.id());
+
+        let mut inner = group::Group::new();
+        inner.add_child(s2);
+        let inner_id = inner.id();
+
+        let mut outer = group::Group::new();
+        outer.add_child(s1);
+        outer.add_child(inner);
+
+        let outer = Shape::from(outer);
+
+        assert_eq!(outer.find(id1).unwrap().id(), id1);
+        assert_eq!(outer.find(id2).unwrap().id(), id2);
+        assert!(outer.includes(inner_id));
+        assert!(!outer.includes(-1));
+        assert!(!Shape::from(ray::Sphere::unit()).includes(id1));
+    }
+
+    #[test]
+    fn only_primitives_have_surfaces() {
+
+        let g = Shape::from(group::Group::new());
+        assert!(g.normal_at(geo::Point3::new(0.0, 0.0, 0.0)).is_none());
+        assert!(g.material().is_none());
+
+        let s = Shape::from(ray::Sphere::unit());
+        assert_eq!(s.normal_at(geo::Point3::new(0.0, 0.0,-1.0)), Some(geo::Vec3::new(0.0, 0.0,-1.0)));
+        assert!(s.material().is_some());
+    }
+}
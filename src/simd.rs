@@ -0,0 +1,525 @@
+use crate::geo;
+use crate::ray;
+use crate::matrix;
+use std::ops;
+
+// Four lanes of geo::Float, the width everything in here works at. On x86_64
+// the lanes live in sse2 registers, which every x86_64 cpu has so there is no
+// runtime detection to do. Elsewhere they are a plain array and vectorising
+// the loops is left to the compiler.
+//
+// The vector math works on four vectors at once, a coordinate per register,
+// rather than on one geo::Vec3 or color::Color. Those stay scalar: three
+// floats fill less than a register, and the shuffles a horizontal dot or
+// cross needs cost about what they save.
+
+#[cfg(all(target_arch = "x86_64", not(feature = "f32")))]
+mod lanes {
+    use std::ops;
+    use std::arch::x86_64::*;
+    use crate::geo::Float;
+
+    // an sse2 register only holds two f64, so four take a pair of them
+    #[derive(Debug,Copy,Clone)]
+    pub struct F4(__m128d, __m128d);
+
+    impl F4 {
+
+        pub fn new(a:Float, b:Float, c:Float, d:Float) -> F4 {
+            // _mm_set_pd takes the high lane first
+            // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+            unsafe { F4(_mm_set_pd(b, a), _mm_set_pd(d, c)) }
+        }
+
+        pub fn splat(v:Float) -> F4 {
+            // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+            unsafe { F4(_mm_set1_pd(v), _mm_set1_pd(v)) }
+        }
+
+        pub fn to_array(self) -> [Float;4] {
+
+            let mut out = [0.0;4];
+
+            // SAFETY: sse2 is part of the x86_64 baseline, and each unaligned
+            // store writes two of the four floats out has room for
+            unsafe {
+                _mm_storeu_pd(out.as_mut_ptr(), self.0);
+                _mm_storeu_pd(out.as_mut_ptr().add(2), self.1);
+            }
+
+            out
+        }
+
+        pub fn sqrt(self) -> F4 {
+            // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+            unsafe { F4(_mm_sqrt_pd(self.0), _mm_sqrt_pd(self.1)) }
+        }
+    }
+
+    macro_rules! binary_op {
+        ($trait:ident, $method:ident, $intrinsic:ident) => {
+            impl ops::$trait for F4 {
+                type Output = F4;
+
+                fn $method(self, rhs:F4) -> F4 {
+                    // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+                    unsafe { F4($intrinsic(self.0, rhs.0), $intrinsic(self.1, rhs.1)) }
+                }
+            }
+        };
+    }
+
+    binary_op!(Add, add, _mm_add_pd);
+    binary_op!(Sub, sub, _mm_sub_pd);
+    binary_op!(Mul, mul, _mm_mul_pd);
+    binary_op!(Div, div, _mm_div_pd);
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "f32"))]
+mod lanes {
+    use std::ops;
+    use std::arch::x86_64::*;
+    use crate::geo::Float;
+
+    #[derive(Debug,Copy,Clone)]
+    pub struct F4(__m128);
+
+    impl F4 {
+
+        pub fn new(a:Float, b:Float, c:Float, d:Float) -> F4 {
+            // _mm_set_ps takes the high lane first
+            // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+            unsafe { F4(_mm_set_ps(d, c, b, a)) }
+        }
+
+        pub fn splat(v:Float) -> F4 {
+            // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+            unsafe { F4(_mm_set1_ps(v)) }
+        }
+
+        pub fn to_array(self) -> [Float;4] {
+
+            let mut out = [0.0;4];
+            // SAFETY: sse2 is part of the x86_64 baseline, and the unaligned
+            // store writes exactly the four floats out holds
+            unsafe { _mm_storeu_ps(out.as_mut_ptr(), self.0) };
+            out
+        }
+
+        pub fn sqrt(self) -> F4 {
+            // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+            unsafe { F4(_mm_sqrt_ps(self.0)) }
+        }
+    }
+
+    macro_rules! binary_op {
+        ($trait:ident, $method:ident, $intrinsic:ident) => {
+            impl ops::$trait for F4 {
+                type Output = F4;
+
+                fn $method(self, rhs:F4) -> F4 {
+                    // SAFETY: sse2 is part of the x86_64 baseline, so it is always there
+                    unsafe { F4($intrinsic(self.0, rhs.0)) }
+                }
+            }
+        };
+    }
+
+    binary_op!(Add, add, _mm_add_ps);
+    binary_op!(Sub, sub, _mm_sub_ps);
+    binary_op!(Mul, mul, _mm_mul_ps);
+    binary_op!(Div, div, _mm_div_ps);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod lanes {
+    use std::ops;
+    use crate::geo::Float;
+
+    #[derive(Debug,Copy,Clone)]
+    pub struct F4([Float;4]);
+
+    impl F4 {
+
+        pub fn new(a:Float, b:Float, c:Float, d:Float) -> F4 {
+            F4([a, b, c, d])
+        }
+
+        pub fn splat(v:Float) -> F4 {
+            F4([v;4])
+        }
+
+        pub fn to_array(self) -> [Float;4] {
+            self.0
+        }
+
+        pub fn sqrt(self) -> F4 {
+            F4([self.0[0].sqrt(), self.0[1].sqrt(), self.0[2].sqrt(), self.0[3].sqrt()])
+        }
+    }
+
+    macro_rules! binary_op {
+        ($trait:ident, $method:ident, $op:tt) => {
+            impl ops::$trait for F4 {
+                type Output = F4;
+
+                fn $method(self, rhs:F4) -> F4 {
+                    let (a, b) = (self.0, rhs.0);
+                    F4([a[0] $op b[0], a[1] $op b[1], a[2] $op b[2], a[3] $op b[3]])
+                }
+            }
+        };
+    }
+
+    binary_op!(Add, add, +);
+    binary_op!(Sub, sub, -);
+    binary_op!(Mul, mul, *);
+    binary_op!(Div, div, /);
+}
+
+pub use lanes::F4;
+
+// Four points, a coordinate per register.
+#[derive(Debug,Copy,Clone)]
+pub struct Point3x4 {
+    pub x:F4,
+    pub y:F4,
+    pub z:F4,
+}
+
+// Four vectors, a coordinate per register.
+#[derive(Debug,Copy,Clone)]
+pub struct Vec3x4 {
+    pub x:F4,
+    pub y:F4,
+    pub z:F4,
+}
+
+impl Point3x4 {
+
+    pub fn new(p:&[geo::Point3;4]) -> Point3x4 {
+        Point3x4 {x:F4::new(p[0].x, p[1].x, p[2].x, p[3].x),
+                  y:F4::new(p[0].y, p[1].y, p[2].y, p[3].y),
+                  z:F4::new(p[0].z, p[1].z, p[2].z, p[3].z)}
+    }
+
+    pub fn splat(p:geo::Point3) -> Point3x4 {
+        Point3x4 {x:F4::splat(p.x), y:F4::splat(p.y), z:F4::splat(p.z)}
+    }
+
+    pub fn to_array(self) -> [geo::Point3;4] {
+
+        let (x, y, z) = (self.x.to_array(), self.y.to_array(), self.z.to_array());
+        [0, 1, 2, 3].map(|i| geo::Point3::new(x[i], y[i], z[i]))
+    }
+}
+
+impl Vec3x4 {
+
+    pub fn new(v:&[geo::Vec3;4]) -> Vec3x4 {
+        Vec3x4 {x:F4::new(v[0].x, v[1].x, v[2].x, v[3].x),
+                y:F4::new(v[0].y, v[1].y, v[2].y, v[3].y),
+                z:F4::new(v[0].z, v[1].z, v[2].z, v[3].z)}
+    }
+
+    pub fn splat(v:geo::Vec3) -> Vec3x4 {
+        Vec3x4 {x:F4::splat(v.x), y:F4::splat(v.y), z:F4::splat(v.z)}
+    }
+
+    pub fn to_array(self) -> [geo::Vec3;4] {
+
+        let (x, y, z) = (self.x.to_array(), self.y.to_array(), self.z.to_array());
+        [0, 1, 2, 3].map(|i| geo::Vec3::new(x[i], y[i], z[i]))
+    }
+
+    pub fn len(&self) -> F4 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn norm(&self) -> Vec3x4 {
+        *self/self.len()
+    }
+
+    pub fn dot(&self, other:Self) -> F4 {
+        self.x*other.x + self.y*other.y + self.z*other.z
+    }
+
+    pub fn cross(&self, other:Self) -> Vec3x4 {
+        Vec3x4 {x:self.y*other.z - self.z*other.y,
+                y:self.z*other.x - self.x*other.z,
+                z:self.x*other.y - self.y*other.x}
+    }
+}
+
+impl ops::Sub for Point3x4 {
+    type Output = Vec3x4;
+
+    fn sub(self, rhs:Point3x4) -> Vec3x4 {
+        Vec3x4 {x:self.x - rhs.x, y:self.y - rhs.y, z:self.z - rhs.z}
+    }
+}
+
+impl ops::Add for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn add(self, rhs:Vec3x4) -> Vec3x4 {
+        Vec3x4 {x:self.x + rhs.x, y:self.y + rhs.y, z:self.z + rhs.z}
+    }
+}
+
+impl ops::Sub for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn sub(self, rhs:Vec3x4) -> Vec3x4 {
+        Vec3x4 {x:self.x - rhs.x, y:self.y - rhs.y, z:self.z - rhs.z}
+    }
+}
+
+// each vector scaled by its own lane
+impl ops::Mul<F4> for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn mul(self, rhs:F4) -> Vec3x4 {
+        Vec3x4 {x:self.x*rhs, y:self.y*rhs, z:self.z*rhs}
+    }
+}
+
+impl ops::Div<F4> for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn div(self, rhs:F4) -> Vec3x4 {
+        Vec3x4 {x:self.x/rhs, y:self.y/rhs, z:self.z/rhs}
+    }
+}
+
+// as Matrix4 * Point3, points carry an implicit w of 1
+impl ops::Mul<Point3x4> for matrix::Matrix4 {
+    type Output = Point3x4;
+
+    fn mul(self, rhs:Point3x4) -> Point3x4 {
+
+        let e = |r:usize, c:usize| F4::splat(self.get(r,c));
+
+        let p = Point3x4 {x:e(0,0)*rhs.x + e(0,1)*rhs.y + e(0,2)*rhs.z + e(0,3),
+                          y:e(1,0)*rhs.x + e(1,1)*rhs.y + e(1,2)*rhs.z + e(1,3),
+                          z:e(2,0)*rhs.x + e(2,1)*rhs.y + e(2,2)*rhs.z + e(2,3)};
+
+        if self.get(3,0) == 0.0 && self.get(3,1) == 0.0 && self.get(3,2) == 0.0 && self.get(3,3) == 1.0 {
+            return p;
+        }
+
+        let w = e(3,0)*rhs.x + e(3,1)*rhs.y + e(3,2)*rhs.z + e(3,3);
+        Point3x4 {x:p.x/w, y:p.y/w, z:p.z/w}
+    }
+}
+
+// as Matrix4 * Vec3, vectors carry an implicit w of 0
+impl ops::Mul<Vec3x4> for matrix::Matrix4 {
+    type Output = Vec3x4;
+
+    fn mul(self, rhs:Vec3x4) -> Vec3x4 {
+
+        let e = |r:usize, c:usize| F4::splat(self.get(r,c));
+
+        Vec3x4 {x:e(0,0)*rhs.x + e(0,1)*rhs.y + e(0,2)*rhs.z,
+                y:e(1,0)*rhs.x + e(1,1)*rhs.y + e(1,2)*rhs.z,
+                z:e(2,0)*rhs.x + e(2,1)*rhs.y + e(2,2)*rhs.z}
+    }
+}
+
+// Four rays laid out a coordinate per register, so each lane follows its own
+// ray through the same arithmetic.
+#[derive(Debug,Copy,Clone)]
+pub struct Ray4 {
+    pub orig:Point3x4,
+    pub dir:Vec3x4,
+}
+
+impl Ray4 {
+
+    pub fn new(rays:&[ray::Ray;4]) -> Ray4 {
+        Ray4 {orig:Point3x4::new(&rays.map(|r| r.orig)),
+              dir:Vec3x4::new(&rays.map(|r| r.dir))}
+    }
+
+    // origins are points and pick up the translation, directions do not
+    pub fn transform(&self, m:&matrix::Matrix) -> Ray4 {
+        Ray4 {orig:*m*self.orig, dir:*m*self.dir}
+    }
+
+    // both roots for every lane, a negative discriminant marks a miss
+    fn sphere_roots(&self, s:&ray::Sphere) -> ([geo::Float;4], [geo::Float;4], [geo::Float;4]) {
+
+        let r = self.transform(&s.inverse());
+        let sphere_to_ray = r.orig - Point3x4::splat(s.orig);
+
+        let a = r.dir.dot(r.dir);
+        let b = F4::splat(2.0)*r.dir.dot(sphere_to_ray);
+        let c = sphere_to_ray.dot(sphere_to_ray) - F4::splat(1.0);
+
+        let disc = b*b - F4::splat(4.0)*a*c;
+        let root = disc.sqrt();
+        let two_a = F4::splat(2.0)*a;
+
+        let t1 = (F4::splat(0.0) - b - root)/two_a;
+        let t2 = (F4::splat(0.0) - b + root)/two_a;
+
+        (disc.to_array(), t1.to_array(), t2.to_array())
+    }
+
+    // what Ray::intersect gives for each of the four rays
+    pub fn intersect(&self, s:&ray::Sphere) -> [Vec<ray::Isect>;4] {
+
+        let (disc, t1, t2) = self.sphere_roots(s);
+
+        let lane = |i:usize| {
+            if disc[i] < 0.0 {
+                return vec![];
+            }
+            vec![ray::Isect::isect(t1[i], s.id()), ray::Isect::isect(t2[i], s.id())]
+        };
+
+        [lane(0), lane(1), lane(2), lane(3)]
+    }
+
+    // what Ray::hit gives for each of the four rays, the nearest positive t,
+    // infinite where the sphere is missed
+    pub fn hit(&self, s:&ray::Sphere) -> [geo::Float;4] {
+
+        let (disc, t1, t2) = self.sphere_roots(s);
+        let mut ret = [geo::Float::INFINITY;4];
+
+        for i in 0..4 {
+            if disc[i] < 0.0 {
+                continue;
+            }
+            if t1[i] > 0.0 {
+                ret[i] = t1[i];
+            } else if t2[i] > 0.0 {
+                ret[i] = t2[i];
+            }
+        }
+
+        ret
+    }
+}
+
+// Eight rays as two Ray4 halves. Without avx, which would need detecting at
+// runtime, sse2 has no wider register to put them in, so this buys fewer calls
+// and a longer run of independent arithmetic rather than wider instructions.
+#[derive(Debug,Copy,Clone)]
+pub struct Ray8(Ray4, Ray4);
+
+impl Ray8 {
+
+    pub fn new(rays:&[ray::Ray;8]) -> Ray8 {
+        Ray8(Ray4::new(&[rays[0], rays[1], rays[2], rays[3]]),
+             Ray4::new(&[rays[4], rays[5], rays[6], rays[7]]))
+    }
+
+    // what Ray::hit gives for each of the eight rays
+    pub fn hit(&self, s:&ray::Sphere) -> [geo::Float;8] {
+
+        let (lo, hi) = (self.0.hit(s), self.1.hit(s));
+        [lo[0], lo[1], lo[2], lo[3], hi[0], hi[1], hi[2], hi[3]]
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lanes() {
+
+        let a = F4::new(1.0, 2.0, 3.0, 4.0);
+        let b = F4::splat(2.0);
+
+        assert_eq!((a + b).to_array(), [3.0, 4.0, 5.0, 6.0]);
+        assert_eq!((a - b).to_array(), [-1.0, 0.0, 1.0, 2.0]);
+        assert_eq!((a * b).to_array(), [2.0, 4.0, 6.0, 8.0]);
+        assert_eq!((a / b).to_array(), [0.5, 1.0, 1.5, 2.0]);
+        assert_eq!((a * a).sqrt().to_array(), [1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn vector_math_matches_scalar() {
+
+        let a = [geo::Vec3::new(1.0, 2.0, 3.0), geo::Vec3::new(-1.0, 0.5, 0.0),
+                 geo::Vec3::new(0.0, 0.0, 2.0), geo::Vec3::new(3.0,-4.0, 1.0)];
+        let b = [geo::Vec3::new(2.0, 3.0, 4.0), geo::Vec3::new(0.0, 1.0, 0.0),
+                 geo::Vec3::new(1.0, 0.0, 0.0), geo::Vec3::new(-2.0, 1.0, 5.0)];
+
+        let (a4, b4) = (Vec3x4::new(&a), Vec3x4::new(&b));
+        let dot   = a4.dot(b4).to_array();
+        let cross = a4.cross(b4).to_array();
+        let norm  = a4.norm().to_array();
+
+        for i in 0..4 {
+            assert!((dot[i] - a[i].dot(b[i])).abs() < geo::EPSILON);
+            assert_eq!(cross[i], a[i].cross(b[i]));
+            assert_eq!(norm[i], a[i].norm());
+        }
+    }
+
+    #[test]
+    fn matrix_products_match_scalar() {
+
+        let m = matrix::Matrix::rotate_z(1.0).then_scale(2.0, 1.0, 3.0).then_translate(1.0,-2.0, 0.5);
+
+        let p = [geo::Point3::new(1.0, 2.0, 3.0), geo::Point3::new(0.0, 0.0, 0.0),
+                 geo::Point3::new(-1.0, 4.0, 2.0), geo::Point3::new(5.0, 5.0,-5.0)];
+        let v = [geo::Vec3::new(1.0, 2.0, 3.0), geo::Vec3::new(0.0, 1.0, 0.0),
+                 geo::Vec3::new(-1.0, 4.0, 2.0), geo::Vec3::new(5.0, 5.0,-5.0)];
+
+        let p4 = (m*Point3x4::new(&p)).to_array();
+        let v4 = (m*Vec3x4::new(&v)).to_array();
+
+        for i in 0..4 {
+            assert_eq!(p4[i], m*p[i]);
+            assert_eq!(v4[i], m*v[i]);
+        }
+    }
+
+    #[test]
+    fn batched_sphere_matches_scalar() {
+
+        let mut s = ray::Sphere::unit();
+        s.set_transform(matrix::Matrix::scale(2.0, 1.0, 1.0).then_translate(0.5, 0.0, 0.0)).unwrap();
+
+        // a hit, a miss, a tangent and one starting inside
+        let rays = [ray::Ray::new(geo::Point3::new(0.0, 0.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0)),
+                    ray::Ray::new(geo::Point3::new(0.0, 2.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0)),
+                    ray::Ray::new(geo::Point3::new(0.5, 1.0,-5.0), geo::Vec3::new(0.0, 0.0, 1.0)),
+                    ray::Ray::new(geo::Point3::new(0.0, 0.0, 0.0), geo::Vec3::new(1.0, 1.0, 0.0))];
+
+        let r4 = Ray4::new(&rays);
+        let xs = r4.intersect(&s);
+        let ts = r4.hit(&s);
+
+        for i in 0..4 {
+            let e = rays[i].intersect(&s);
+            assert_eq!(xs[i], e);
+
+            let h = ray::Isect::hit(e);
+            if h.id < 0 {
+                assert_eq!(ts[i], geo::Float::INFINITY);
+            } else {
+                assert!((ts[i] - h.t).abs() < geo::EPSILON);
+            }
+
+            let t = rays[i].hit(&s);
+            assert!(ts[i] == t || (ts[i] - t).abs() < geo::EPSILON);
+        }
+
+        let r8 = Ray8::new(&[rays[0], rays[1], rays[2], rays[3], rays[3], rays[2], rays[1], rays[0]]);
+        let t8 = r8.hit(&s);
+
+        for i in 0..4 {
+            assert_eq!(t8[i], ts[i]);
+            assert_eq!(t8[7 - i], ts[i]);
+        }
+    }
+
+}